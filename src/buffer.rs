@@ -1,39 +1,61 @@
-use std::mem::{align_of, size_of, transmute};
-use std::ops::{Deref, RangeBounds};
-use std::process::abort;
+use std::mem::{align_of, size_of, ManuallyDrop};
+use std::ops::{Bound, Deref, RangeBounds};
 use std::{mem, ptr};
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::ptr::slice_from_raw_parts;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use crate::buffer_format::{BufferFormat, Flags};
-use crate::buffer_format::half::FormatHalf;
-use crate::{buffer_mut, buffer_rw, GenericBuffer, ReadableBuffer, ReadonlyBuffer, WritableBuffer};
+use std::string::FromUtf8Error;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+use std::{fs::File, io};
+use crate::buffer_layout::INLINE_WORDS_DEFAULT;
+use crate::buffer_format::{convert_layout, BufferFormat, Flags};
+use crate::ref_cnt::{LocalRefCnt, RefCnt};
+use crate::buffer_format::packed::FormatPacked;
+use crate::stats;
+use crate::config::DefaultConfig;
+use crate::growth::{Doubling, GrowthPolicy};
+use std::marker::PhantomData;
+use crate::{GenericBuffer, GenericReadableBuffer, ReadableBuffer, ReadonlyBuffer};
 use crate::buffer_mut::BufferMutGeneric;
-use crate::util::{align_unaligned_len_to, align_unaligned_ptr_to, dealloc, empty_sentinel, min, realloc_buffer, realloc_buffer_counted};
-
-pub type Buffer = BufferGeneric;
+use crate::buffer_rw::BufferRWGeneric;
+use crate::external::{owner_header, vec_header, ExternalHeader};
+use crate::storage::{ExternalStorage, HeapStorage, StaticStorage, StorageVTable};
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+use crate::mmap;
+use crate::util::{aligned_cap, dealloc_counted, empty_sentinel, fits_meta, meta_align, meta_of, realloc_buffer, realloc_buffer_counted, realloc_buffer_counted_aligned, Metadata, WeakCell, METADATA_SIZE};
+
+crate::buffer_types!(@buffer DefaultConfig, Buffer);
+/// A buffer which uses a non-atomic reference counter, it can't be sent or shared across threads.
+pub type LocalBuffer = BufferGeneric<FormatPacked<LocalRefCnt>>;
+crate::buffer_types!(@weak DefaultConfig, WeakBuffer);
 
 // TODO: once const_generic_expressions are supported calculate INITIAL_CAP the following:
-// INITIAL_CAP = GROWTH_FACTOR * INLINE_SIZE
-const INITIAL_CAP_DEFAULT: usize = (2 * INLINE_SIZE).next_power_of_two();
+// INITIAL_CAP = GROWTH_FACTOR * LAYOUT::INLINE_SIZE
+pub(crate) const INITIAL_CAP_DEFAULT: usize = (2 * INLINE_WORDS_DEFAULT * size_of::<usize>()).next_power_of_two();
+
+/// views are never compacted by default, as compacted parts can't be unsplit and copying
+/// would surprise callers relying on `slice` and `split_off` being zero-copy.
+pub(crate) const COMPACT_DIVISOR_DEFAULT: usize = 0;
 
+/// `COMPACT_DIVISOR` lets `slice` and `split_off` copy their result into a right-sized
+/// allocation if it covers less than `1 / COMPACT_DIVISOR` of the heap allocation it references,
+/// so small views don't keep large allocations alive. A value of 0 disables this.
+/// Views of external and static storage are never compacted, use `unshare` to copy them.
 #[repr(C)]
-pub struct BufferGeneric<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE> = FormatHalf, const GROWTH_FACTOR: usize = 2, const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT, const INLINE_SMALL: bool = true, const STATIC_STORAGE: bool = true, const RETAIN_INDICES: bool = true>(pub(crate) LAYOUT);
+pub struct BufferGeneric<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE> = FormatPacked, GROWTH: GrowthPolicy = Doubling, const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT, const INLINE_SMALL: bool = true, const STATIC_STORAGE: bool = true, const RETAIN_INDICES: bool = true, const COMPACT_DIVISOR: usize = COMPACT_DIVISOR_DEFAULT>(pub(crate) LAYOUT, pub(crate) PhantomData<GROWTH>);
 
-pub(crate) const BASE_INLINE_SIZE: usize = size_of::<BufferGeneric<0, 0, false, false>>() - size_of::<usize>();
-const INLINE_SIZE: usize = min(min(BASE_INLINE_SIZE, buffer_mut::BASE_INLINE_SIZE), buffer_rw::BASE_INLINE_SIZE);
-/// this additional storage is used to store the reference counter and
+/// this additional storage is used to store the metadata and
 /// to align said values properly.
 const ADDITIONAL_BUFFER_CAP: usize = METADATA_SIZE + align_of::<usize>() - 1;
-const METADATA_SIZE: usize = size_of::<usize>() * 1;
 
-unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Send for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {}
-unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Sync for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {}
+unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Send for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> where LAYOUT::RefCntTy: Send {}
+unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Sync for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> where LAYOUT::RefCntTy: Sync {}
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
 
     #[inline]
     pub(crate) fn is_static(&self) -> bool {
@@ -45,11 +67,172 @@ BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE,
         INLINE_SMALL && self.0.flags().is_inlined()
     }
 
+    #[inline]
+    pub(crate) fn is_external(&self) -> bool {
+        STATIC_STORAGE && self.0.flags().is_external()
+    }
+
+    /// Creates a read-only buffer referencing the bytes of `owner` without copying them.
+    /// The owner is kept alive until the last buffer referencing its bytes gets dropped.
+    ///
+    /// Note that the bytes get copied if the buffer doesn't support static storage.
+    pub fn from_owner<T: AsRef<[u8]> + Send + Sync + 'static>(owner: T) -> Self {
+        if !STATIC_STORAGE {
+            // external storage shares its flags with static storage
+            return Self::from(owner.as_ref().to_vec());
+        }
+        let header = owner_header(owner, ExternalStorage::<LAYOUT::RefCntTy>::VTABLE);
+        let len = unsafe { (*header).len };
+        Self(LAYOUT::new_reference(len, 0, len, 0, 0, header.cast::<u8>(), LAYOUT::FlagsTy::new_external()), PhantomData)
+    }
+
+    /// Converts the unread data of the buffer into a boxed slice, this reuses the buffer's
+    /// allocation if possible.
+    pub fn into_boxed_slice(mut self) -> Box<[u8]> {
+        self.advance_view(self.0.rdx());
+        let vec: Vec<u8> = self.into();
+        vec.into_boxed_slice()
+    }
+
+    /// Converts the unread data of the buffer into a string if it's valid UTF-8, this reuses
+    /// the buffer's allocation if possible.
+    pub fn try_into_string(mut self) -> Result<String, FromUtf8Error> {
+        self.advance_view(self.0.rdx());
+        String::from_utf8(self.into())
+    }
+
+    /// Maps the whole `file` into memory and returns a read-only buffer referencing it.
+    /// The mapping is shared by all clones and splits of the buffer and
+    /// gets unmapped once the last of them gets dropped.
+    ///
+    /// # Safety
+    /// the file may not be modified or truncated while the buffer or
+    /// any buffer derived from it is alive.
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    pub unsafe fn map_file(file: &File) -> io::Result<Self> {
+        let len = file.metadata()?.len() as usize;
+        unsafe { Self::map_file_range(file, 0, len) }
+    }
+
+    /// Maps `len` bytes of `file` starting at `offset` into memory and returns a read-only
+    /// buffer referencing them, see `map_file`.
+    /// Fails with `UnexpectedEof` if the range exceeds the end of the file.
+    ///
+    /// # Safety
+    /// see `map_file`
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    pub unsafe fn map_file_range(file: &File, offset: u64, len: usize) -> io::Result<Self> {
+        if !STATIC_STORAGE {
+            // external storage shares its flags with static storage
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "mapped buffers require static storage support"));
+        }
+        let file_len = file.metadata()?.len();
+        if offset.checked_add(len as u64).is_none_or(|end| end > file_len) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the mapped range exceeds the end of the file"));
+        }
+        if len == 0 {
+            // empty mappings aren't supported by mmap
+            return Ok(Self::new());
+        }
+        let (header, data_offset) = unsafe { mmap::map::<LAYOUT::RefCntTy>(file, offset, len)? };
+        Ok(Self(LAYOUT::new_reference(len, 0, len, 0, data_offset, header.cast::<u8>(), LAYOUT::FlagsTy::new_external()), PhantomData))
+    }
+
     /// SAFETY: this is only safe to call if the buffer isn't inlined and isn't static.
     #[inline]
     pub(crate) unsafe fn is_only(&self) -> bool {
         let meta_ptr = unsafe { self.meta_ptr() };
-        unsafe { &*meta_ptr.cast::<AtomicUsize>() }.load(Ordering::Acquire) == 1
+        LAYOUT::RefCntTy::load(unsafe { &*meta_ptr.cast::<AtomicUsize>() }) == 1
+    }
+
+    /// returns the operations of the storage this buffer references.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    #[inline]
+    pub(crate) unsafe fn vtable(&self) -> &'static StorageVTable {
+        if self.is_static() || (!INLINE_SMALL && !STATIC_STORAGE && self.0.ptr_reference() == empty_sentinel()) {
+            StaticStorage::VTABLE
+        } else if self.is_external() {
+            unsafe { ExternalHeader::vtable(self.0.ptr_reference()) }
+        } else {
+            HeapStorage::<LAYOUT::RefCntTy>::VTABLE
+        }
+    }
+
+    /// returns whether this is the only buffer referencing its storage and the storage
+    /// may be mutated, if so weak buffers can't upgrade anymore.
+    ///
+    /// SAFETY: this is only safe to call if the buffer isn't inlined.
+    #[inline]
+    pub(crate) unsafe fn claim_unique(&self) -> bool {
+        unsafe { (self.vtable().is_unique)(self.0.ptr_reference(), self.0.cap_reference()) }
+    }
+
+    /// Converts this buffer into a mutable buffer without copying its data, this
+    /// only succeeds if the buffer is inlined or if it's the only buffer referencing
+    /// its allocation. Otherwise the buffer is returned unchanged.
+    ///
+    /// Inlined data is copied into a new allocation if it doesn't fit into the inline
+    /// storage of the mutable buffer.
+    pub fn try_into_mut<OtherLayout: BufferFormat<INLINE_SMALL, false>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool>(self) -> Result<BufferMutGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>, Self> {
+        // static and external buffers are never unique as they can't be written to
+        if !self.is_inlined() && !unsafe { self.claim_unique() } {
+            return Err(self);
+        }
+        Ok(BufferMutGeneric(convert_layout::<INLINE_SMALL, STATIC_STORAGE, false, _, _>(self.into_layout(), ADDITIONAL_BUFFER_CAP), PhantomData).adopt_indices())
+    }
+
+    /// applies `RETAIN_INDICES` to the indices of a buffer that was converted from another buffer type.
+    #[inline]
+    fn adopt_indices(mut self) -> Self {
+        if !RETAIN_INDICES {
+            self.0.set_rdx(0);
+        }
+        self
+    }
+
+    /// takes the layout out of the buffer without releasing the storage it references.
+    #[inline]
+    pub(crate) fn into_layout(self) -> LAYOUT {
+        let this = ManuallyDrop::new(self);
+        unsafe { ptr::read(&this.0) }
+    }
+
+    /// takes the layout out of the buffer, static and external data is copied into
+    /// a new allocation so that the returned layout is either inlined or references
+    /// heap storage.
+    pub(crate) fn into_owned_layout(self) -> LAYOUT {
+        if self.is_static() || self.is_external() {
+            let len = self.0.len_reference();
+            let cap = len + ADDITIONAL_BUFFER_CAP;
+            let alloc = unsafe { realloc_buffer_counted(self.0.ptr(), self.0.offset_reference(), len, cap) };
+            if self.is_static() {
+                stats::on_static_promotion();
+            }
+            stats::on_conversion_copy();
+            // `self` gets dropped here which releases external storage
+            return LAYOUT::new_reference(len, cap, self.0.wrx_reference(), self.0.rdx_reference(), 0, alloc, LAYOUT::FlagsTy::new_reference());
+        }
+        self.into_layout()
+    }
+
+    /// Creates a weak buffer referencing the same data as this buffer which
+    /// doesn't keep the underlying allocation alive.
+    pub fn downgrade(&self) -> WeakBufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+        if self.is_inlined() || self.is_static() {
+            // the data of these buffers can't be freed, so we don't need a weak cell
+            return WeakBufferGeneric {
+                cell: ptr::null(),
+                layout: self.0.clone(),
+                _growth: PhantomData,
+            };
+        }
+        let cell = unsafe { WeakCell::acquire(self.meta_ptr().cast()) };
+        WeakBufferGeneric {
+            cell,
+            layout: self.0.clone(),
+            _growth: PhantomData,
+        }
     }
 
     #[inline]
@@ -61,36 +244,127 @@ BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE,
         unsafe { self.0.ptr().add(self.0.offset() + self.0.rdx()) }
     }
 
+    /// returns the number of bytes the storage of the buffer provides from the start of its view.
     #[inline]
-    pub(crate) fn capacity(&self) -> usize {
+    pub fn capacity(&self) -> usize {
         // for inlined buffers we always have INLINE_SIZE space
         if self.is_inlined() {
-            return INLINE_SIZE;
+            return LAYOUT::INLINE_SIZE;
         }
-        self.get_capacity_outlined()
+        self.0.len_reference()
     }
 
     /// SAFETY: this may only be called if the buffer isn't
     /// inlined and isn't a static buffer
     #[inline]
     pub(crate) unsafe fn meta_ptr(&self) -> *mut u8 {
-        unsafe { align_unaligned_ptr_to::<{ align_of::<usize>() }, METADATA_SIZE>(self.0.ptr_reference(), self.get_capacity_outlined()) }
+        unsafe { layout_meta(&self.0) }.cast::<u8>()
+    }
+
+    /// returns the data of the buffer's view, including the bytes that were read already.
+    #[inline]
+    fn view(&self) -> &[u8] {
+        unsafe { &*slice_from_raw_parts(self.0.ptr().add(self.0.offset()), self.0.wrx()) }
+    }
+
+    /// moves the start of the buffer's view `amount` bytes forward and resets the reader index.
+    #[inline]
+    fn advance_view(&mut self, amount: usize) {
+        self.0.set_offset(self.0.offset() + amount);
+        self.0.set_len(self.0.len() - amount);
+        self.0.set_wrx(self.0.wrx() - amount);
+        self.0.set_rdx(0);
+    }
+
+    /// creates an inlined buffer containing `data`, which has to fit into the inline storage.
+    #[inline]
+    fn new_inlined_from(data: &[u8], rdx: usize) -> Self {
+        let mut ret = Self(LAYOUT::new_inlined(data.len(), 0, data), PhantomData);
+        ret.0.set_wrx_inlined(data.len());
+        ret.0.set_rdx_inlined(rdx);
+        ret
+    }
+
+    /// copies the data of the buffer's view into a new allocation that fits it.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    unsafe fn compact(&mut self) {
+        let len = self.len();
+        // external storage has no alignment of its own
+        let align = if self.is_external() { 1 } else { unsafe { meta_align(self.0.ptr_reference(), self.0.cap_reference()) } };
+        let cap = aligned_cap(len + ADDITIONAL_BUFFER_CAP, align);
+        let alloc = unsafe { realloc_buffer_counted_aligned(self.0.ptr(), self.0.offset_reference(), len, cap, align) };
+        let compacted = Self(LAYOUT::new_reference(len, cap, len, self.0.rdx_reference(), 0, alloc, LAYOUT::FlagsTy::new_reference()), PhantomData);
+        // dropping the old buffer releases its reference to the storage
+        drop(mem::replace(self, compacted));
+    }
+
+    /// compacts the buffer if its view covers less than `1 / COMPACT_DIVISOR` of its allocation.
+    #[inline]
+    fn compact_if_sparse(&mut self) {
+        // external storage is handed out zero-copy on purpose, e.g. mapped files
+        if COMPACT_DIVISOR == 0 || self.is_inlined() || self.is_static() || self.is_external() {
+            return;
+        }
+        if self.len().saturating_mul(COMPACT_DIVISOR) < self.0.cap_reference() {
+            unsafe { self.compact(); }
+        }
+    }
+
+    /// Copies the data of this buffer into a right-sized allocation, so it no longer
+    /// keeps storage alive that it shares with other buffers. Data that fits into the
+    /// inline storage is moved inline instead.
+    ///
+    /// If this is the only buffer referencing its allocation, the allocation is shrunk.
+    pub fn unshare(&mut self) {
+        if self.is_inlined() || self.is_static() {
+            // inlined buffers don't share anything and static data is never freed
+            return;
+        }
+        if self.try_demote() {
+            return;
+        }
+        if !self.is_external() && unsafe { self.is_only() } {
+            self.shrink();
+            return;
+        }
+        unsafe { self.compact(); }
+    }
+
+    /// moves the data into the inline storage if it fits there and releases the storage
+    /// the buffer referenced, so small views don't keep large allocations alive.
+    fn try_demote(&mut self) -> bool {
+        if !INLINE_SMALL || self.is_inlined() || self.is_static() {
+            // static data is never freed, so there is nothing to release
+            return false;
+        }
+        if self.len() > LAYOUT::INLINE_SIZE {
+            return false;
+        }
+        // the inline storage can't keep the alignment of the allocation
+        if !self.is_external() && unsafe { meta_align(self.0.ptr_reference(), self.0.cap_reference()) } > 1 {
+            return false;
+        }
+        let inlined = Self::new_inlined_from(self.view(), self.0.rdx_reference());
+        // dropping the old buffer releases its reference to the storage
+        drop(mem::replace(self, inlined));
+        true
     }
 
     #[inline]
     unsafe fn increment_ref_cnt(&self) {
-        unsafe { &*self.meta_ptr().cast::<AtomicUsize>() }.fetch_add(1, Ordering::AcqRel);
+        unsafe { (self.vtable().clone)(self.0.ptr_reference(), self.0.cap_reference()); }
     }
 
     #[inline]
     unsafe fn decrement_ref_cnt(&self) -> usize {
-        unsafe { &*self.meta_ptr().cast::<AtomicUsize>() }.fetch_sub(1, Ordering::AcqRel) - 1
+        LAYOUT::RefCntTy::decrement(unsafe { &*self.meta_ptr().cast::<AtomicUsize>() }) - 1
     }
 
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool>
-GenericBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+GenericBuffer for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     #[inline]
     fn new() -> Self {
         if !INLINE_SMALL && STATIC_STORAGE {
@@ -99,9 +373,9 @@ GenericBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL
         }
 
         if INLINE_SMALL {
-            Self(LAYOUT::new_inlined(INLINE_SIZE, 0, [0; 3]))
+            Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &[]), PhantomData)
         } else {
-            Self(LAYOUT::new_reference(0, 0, 0, 0, 0, empty_sentinel(), LAYOUT::FlagsTy::new_reference()))
+            Self(LAYOUT::new_reference(0, 0, 0, 0, 0, empty_sentinel(), LAYOUT::FlagsTy::new_reference()), PhantomData)
         }
     }
 
@@ -110,39 +384,53 @@ GenericBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL
         self.0.wrx()
     }
 
+    /// unless `RETAIN_INDICES` is set, this releases the buffer's storage.
     #[inline]
     fn clear(&mut self) {
-        let _ = mem::replace(self, BufferGeneric::new());
+        if RETAIN_INDICES {
+            self.0.set_rdx(0);
+            self.0.set_wrx(0);
+        } else {
+            let _ = mem::take(self);
+        }
     }
 
     /// this can lead to a second buffer being allocated while the first buffer staying
     /// alive. this can happen if the reference count is larger than 1.
+    ///
+    /// Buffers whose data fits into the inline storage are moved inline, even if
+    /// their storage is shared.
     fn shrink(&mut self) {
         if self.is_inlined() {
             // we have nothing to do as the buffer is stored in line
             return;
         }
-        if self.is_static() {
-            // we have nothing to do for static buffers
+        if self.try_demote() {
+            return;
+        }
+        if self.is_static() || self.is_external() {
+            // we have nothing to do for static and external buffers
             return;
         }
         if !unsafe { self.is_only() } {
             // For now we just nop for buffers we don't completely own.
             return;
         }
-        let target_cap = self.0.len_reference() + ADDITIONAL_BUFFER_CAP;
-        if self.0.cap_reference() <= target_cap {
+        let old = self.0.ptr_reference();
+        let cap = self.0.cap_reference();
+        let align = unsafe { meta_align(old, cap) };
+        let target_cap = aligned_cap(self.0.len_reference() + ADDITIONAL_BUFFER_CAP, align);
+        if cap <= target_cap {
             // we have nothing to do as our capacity is already as small as possible
             return;
         }
-        let old = self.0.ptr_reference();
-        let alloc = unsafe { realloc_buffer_counted(old, self.0.offset_reference(), self.0.len_reference(), target_cap) };
-        let cap = self.0.cap_reference();
+        let alloc = unsafe { realloc_buffer_counted_aligned(old, self.0.offset_reference(), self.0.len_reference(), target_cap, align) };
         if unsafe { self.decrement_ref_cnt() } == 0 {
-            unsafe { dealloc(old, cap); }
+            unsafe { dealloc_counted(old, cap); }
         }
         self.0.set_ptr_reference(alloc);
-        self.0.set_cap_reference(self.0.len_reference());
+        self.0.set_cap_reference(target_cap);
+        self.0.set_offset_reference(0);
     }
 
     #[inline]
@@ -155,34 +443,30 @@ GenericBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL
         }
     }
 
+    /// small parts keep referencing the storage, so `unsplit` can rejoin them, `shrink`
+    /// moves them inline. see `COMPACT_DIVISOR` for when larger parts are copied.
     #[inline]
     fn split_off(&mut self, offset: usize) -> Self {
-        let idx = self.0.offset() + self.0.rdx() + offset;
-        assert!(self.len() > idx, "tried splitting buffer with length {} at {}", self.0.len(), idx);
+        let idx = self.0.rdx() + offset;
+        assert!(self.len() >= idx, "tried splitting buffer with length {} at {}", self.len(), idx);
+        // inlined buffers get copied, so both parts keep the data at its position in the storage
         let mut other = self.clone();
-
-        self.0.set_len(idx);
-        other.0.set_offset(idx);
-
+        other.advance_view(idx);
+        other.compact_if_sparse();
+        self.truncate(idx);
+        self.compact_if_sparse();
         other
     }
 
+    /// small parts keep referencing the storage, so `unsplit` can rejoin them, `shrink`
+    /// moves them inline.
     #[inline]
     fn split_to(&mut self, offset: usize) -> Self {
-        let other = if self.is_inlined() {
-            let mut other = Self(LAYOUT::new_inlined(self.0.len_inlined() + offset, self.0.offset_inlined(), unsafe { *self.0.ptr_inlined().cast::<[usize; 3]>() }));
-            other.0.set_wrx_inlined(self.0.wrx_inlined());
-            other.0.set_rdx_inlined(self.0.rdx_inlined());
-            other
-        } else {
-            unsafe { self.increment_ref_cnt(); }
-            let other = Self(LAYOUT::new_reference(self.0.wrx_reference() + offset, self.0.cap_reference(), self.0.wrx_reference(), self.0.rdx_reference(), self.0.offset_reference(), self.0.ptr_reference(), self.0.flags()));
-            other
-        };
-        self.0.set_len(self.0.wrx() + offset);
-        self.0.set_rdx(0);
-        self.0.set_offset(self.0.offset() + self.0.wrx() + offset);
-        self.0.set_wrx(0);
+        let idx = self.0.rdx() + offset;
+        assert!(self.len() >= idx, "tried splitting buffer with length {} at {}", self.len(), idx);
+        let mut other = self.clone();
+        other.truncate(idx);
+        self.advance_view(idx);
         other
     }
 
@@ -191,19 +475,20 @@ GenericBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL
     }
 
     fn unsplit(&mut self, other: Self) {
-        self.try_unsplit(other).unwrap();
+        if self.try_unsplit(other).is_err() {
+            panic!("Unsplitting only works on buffers that are next to each other");
+        }
     }
 
     fn try_unsplit(&mut self, other: Self) -> Result<(), Self> {
         if self.0.flags() != other.0.flags() {
             return Err(other);
         }
-        let own_off = self.0.offset();
-        let other_off = other.0.offset();
-        let (min, max) = if own_off < other_off {
-            (&self, &other)
+        let this = &*self;
+        let (min, max) = if this.0.offset() < other.0.offset() {
+            (this, &other)
         } else {
-            (&other, &self)
+            (&other, this)
         };
 
         // check if the left buffer still has uninit data
@@ -218,32 +503,33 @@ GenericBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL
         }
 
         // check if ptrs aren't matching
-        if !self.0.flags().is_inlined() && min.0.ptr_reference() != max.0.ptr_reference() {
+        if !self.is_inlined() && min.0.ptr_reference() != max.0.ptr_reference() {
             return Err(other);
         }
 
-        self.0.set_len_inlined(self.0.len() + other.0.len());
-        self.0.set_offset(self.0.offset().min(other.0.offset()));
+        // the combined buffer continues reading where the front part left off
+        let rdx = if RETAIN_INDICES { min.0.rdx() } else { 0 };
+        let len = min.0.len() + max.0.len();
+        let offset = min.0.offset();
+        if self.is_inlined() {
+            // inlined parts have their own storage, so the data of the other part has to be copied over
+            unsafe { ptr::copy_nonoverlapping(other.0.ptr_inlined().add(other.0.offset_inlined()), self.0.ptr_inlined().add(other.0.offset_inlined()), other.0.wrx_inlined()); }
+        }
+        self.0.set_len(len);
+        self.0.set_offset(offset);
         self.0.set_wrx(self.0.wrx() + other.0.wrx());
-        self.0.set_rdx(0);
+        self.0.set_rdx(rdx);
         Ok(())
     }
 
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-ReadableBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+ReadableBuffer for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
 
     #[inline]
     fn remaining(&self) -> usize {
-        self.0.len() - self.0.rdx()
-    }
-
-    #[inline]
-    fn get_slice(&mut self, bytes: usize) -> &[u8] {
-        let ptr = self.ensure_readable(bytes);
-        self.0.set_rdx(self.0.rdx() + bytes);
-        unsafe { &*slice_from_raw_parts(ptr, bytes) }
+        self.0.wrx() - self.0.rdx()
     }
 
     #[inline]
@@ -254,86 +540,113 @@ ReadableBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMAL
     }
 
     fn advance(&mut self, amount: usize) {
-        let new_rdx = self.0.rdx() + amount;
-        assert!(new_rdx < self.0.len());
-        self.0.set_rdx(new_rdx);
+        let remaining = self.remaining();
+        assert!(amount <= remaining, "tried advancing {} bytes but only {} bytes are left", amount, remaining);
+        self.0.set_rdx(self.0.rdx() + amount);
+    }
+
+    #[inline]
+    fn copy_to_slice(&mut self, dst: &mut [u8]) {
+        dst.copy_from_slice(self.get_slice(dst.len()));
     }
 
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-ReadonlyBuffer for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
-    fn slice(&self, range_offset: impl RangeBounds<usize>) -> Self {
-        todo!()
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+GenericReadableBuffer for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+
+    #[inline]
+    fn reset_reader_index(&mut self) {
+        self.0.set_rdx(0);
     }
+
+    #[inline]
+    fn get_slice(&mut self, bytes: usize) -> &[u8] {
+        let ptr = self.ensure_readable(bytes);
+        self.0.set_rdx(self.0.rdx() + bytes);
+        unsafe { &*slice_from_raw_parts(ptr, bytes) }
+    }
+
 }
 
-const MAX_REF_CNT: usize = usize::MAX / 2;
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+ReadonlyBuffer for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    /// slices that fit into the inline storage are copied instead of referencing the storage,
+    /// see `COMPACT_DIVISOR` for when larger slices are copied.
+    ///
+    /// #Panic
+    /// Panics when the range exceeds the remaining bytes.
+    fn slice(&self, range_offset: impl RangeBounds<usize>) -> Self {
+        let remaining = self.remaining();
+        let start = match range_offset.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range_offset.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => remaining,
+        };
+        assert!(start <= end && end <= remaining, "tried slicing buffer with {} remaining bytes at {}..{}", remaining, start, end);
+        let rdx = self.0.rdx();
+        if INLINE_SMALL && end - start <= LAYOUT::INLINE_SIZE {
+            return Self::new_inlined_from(&self.view()[rdx + start..rdx + end], 0);
+        }
+        let mut ret = self.clone();
+        ret.truncate(rdx + end);
+        ret.advance_view(rdx + start);
+        ret.compact_if_sparse();
+        ret
+    }
+}
 
+/// returns the metadata of the storage the layout references, for external
+/// buffers it's located at the start of their header.
+///
+/// SAFETY: the layout may neither be inlined nor static.
 #[inline]
-fn increment_ref_cnt(ref_cnt: &AtomicUsize) {
-    let val = ref_cnt.fetch_add(1, Ordering::AcqRel); // FIXME: can we choose a weaker ordering?
-    if val > MAX_REF_CNT {
-        abort();
+unsafe fn layout_meta<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const INLINE_SMALL: bool, const STATIC_STORAGE: bool>(layout: &LAYOUT) -> *mut Metadata {
+    if STATIC_STORAGE && layout.flags().is_external() {
+        return layout.ptr_reference().cast::<Metadata>();
     }
+    unsafe { meta_of(layout.ptr_reference(), layout.cap_reference()) }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Drop for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Drop for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     fn drop(&mut self) {
         if self.is_inlined() {
             // we don't need to do anything for inlined buffers
             return;
         }
-        if self.is_static() {
-            // we don't need to do anything for static buffers
-            return;
-        }
-        if !INLINE_SMALL && !STATIC_STORAGE && self.0.ptr_reference() == empty_sentinel() {
-            // we don't do anything for empty buffers
-            return;
-        }
-        // fast path for single ref cnt scenarios
-        if unsafe { self.is_only() } {
-            unsafe { dealloc(self.buffer.reference.ptr, self.0.cap_reference()); }
-            return;
-        }
-        let meta_ptr = unsafe { self.meta_ptr() };
-        let ref_cnt = unsafe { &*meta_ptr.cast::<AtomicUsize>() };
-        let remaining = ref_cnt.fetch_sub(1, Ordering::AcqRel) - 1; // FIXME: can we choose a weaker ordering?
-        if remaining == 0 {
-            let cap = self.0.cap_reference();
-            unsafe { dealloc(self.0.ptr_reference().cast::<u8>(), cap); }
-        }
+        unsafe { (self.vtable().drop)(self.0.ptr_reference(), self.0.cap_reference()); }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Clone for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Clone for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     #[inline]
     fn clone(&self) -> Self {
-        if !self.is_inlined() && !self.is_static() {
-            let meta_ptr = unsafe { self.meta_ptr() };
+        if !self.is_inlined() {
             // increase the ref cnt if the buffer isn't inlined
-            increment_ref_cnt(unsafe { &*meta_ptr.cast::<AtomicUsize>() });
+            unsafe { self.increment_ref_cnt(); }
         }
-        Self(self.0.clone())
+        Self(self.0.clone(), PhantomData)
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-AsRef<[u8]> for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+AsRef<[u8]> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     #[inline]
     fn as_ref(&self) -> &[u8] {
-        let ptr = self.0.ptr();
-        let rdx = self.0.rdx();
-        let ptr = unsafe { ptr.add(rdx) };
+        let ptr = unsafe { self.0.ptr().add(self.0.offset() + self.0.rdx()) };
         unsafe { &*slice_from_raw_parts(ptr, self.remaining()) }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Deref for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Deref for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     type Target = [u8];
 
     #[inline]
@@ -342,108 +655,213 @@ Deref for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Borrow<[u8]> for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Borrow<[u8]> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     #[inline]
     fn borrow(&self) -> &[u8] {
         self.as_ref()
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Default for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Default for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     #[inline]
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-From<&'static [u8]> for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<&'static [u8]> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     #[inline]
     fn from(value: &'static [u8]) -> Self {
-        Self(LAYOUT::new_reference(value.len(), value.len(), 0, 0, 0, value as *const [u8] as *mut u8, LAYOUT::FlagsTy::new_static_reference()))
+        Self(LAYOUT::new_reference(value.len(), value.len(), value.len(), 0, 0, value as *const [u8] as *mut u8, LAYOUT::FlagsTy::new_static_reference()), PhantomData)
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Into<Vec<u8>> for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR>> for Vec<u8> {
     #[inline]
-    fn into(self) -> Vec<u8> {
+    fn from(buffer: BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR>) -> Self {
         // FIXME: should we add ADDITIONAL_BUFFER_CAP on realloc?
 
         // handle inlined buffers
-        if self.is_inlined() {
-            let alloc = unsafe { realloc_buffer(self.0.ptr_inlined(), self.0.offset_inlined(), self.0.len_inlined(), self.0.len_inlined()) };
-            return unsafe { Vec::from_raw_parts(alloc, self.0.len(), self.0.len()) };
-        }
-        let len = self.0.len_reference();
-        // try reusing buffer
-        if unsafe { self.is_only() } && self.0.offset_reference() == 0 {
-            let ret = unsafe { Vec::from_raw_parts(self.0.ptr_reference(), len, self.0.cap_reference()) };
-            mem::forget(self);
-            return ret;
+        if buffer.is_inlined() {
+            let alloc = unsafe { realloc_buffer(buffer.0.ptr_inlined(), buffer.0.offset_inlined(), buffer.len(), buffer.len()) };
+            stats::on_hand_over(buffer.len());
+            stats::on_conversion_copy();
+            return unsafe { Vec::from_raw_parts(alloc, buffer.len(), buffer.len()) };
         }
-        // FIXME: should we try to shrink?
-        let buf = unsafe { realloc_buffer(self.0.ptr_reference(), self.0.offset_reference(), len, self.0.cap_reference()) };
-        unsafe { Vec::from_raw_parts(buf, len, self.0.cap_reference()) }
+        // the storage decides whether it can be reused, this consumes our reference
+        let vtable = unsafe { buffer.vtable() };
+        let this = ManuallyDrop::new(buffer);
+        unsafe { (vtable.to_vec)(this.0.ptr_reference(), this.0.cap_reference(), this.0.offset_reference(), this.0.wrx_reference()) }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-From<Vec<u8>> for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<Vec<u8>> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     fn from(mut value: Vec<u8>) -> Self {
         let ptr = value.as_mut_ptr();
         let cap = value.capacity();
-        let mut len = value.len();
+        let len = value.len();
         // handle small buffers
-        if INLINE_SMALL && len <= INLINE_SIZE {
+        if INLINE_SMALL && len <= LAYOUT::INLINE_SIZE {
             // FIXME: should we instead keep the small buffer if it exists already and doesn't cost us anything?
-            let mut ret = Self(LAYOUT::new_inlined(len, 0, [0; 3]));
-            unsafe { ptr::copy_nonoverlapping(ptr, ret.0.ptr_inlined(), len); }
+            let mut ret = Self(LAYOUT::new_inlined(len, 0, &value), PhantomData);
+            ret.0.set_wrx_inlined(len);
             return ret;
         }
+        if !fits_meta(ptr, len, cap) {
+            // there is no room for the metadata without clobbering the vec's spare capacity,
+            // so keep the vec as it is and store the metadata in a side allocation instead.
+            // this has to happen eagerly as clones only get a shared reference to the buffer.
+            if STATIC_STORAGE {
+                let header = vec_header::<LAYOUT::RefCntTy>(value);
+                return Self(LAYOUT::new_reference(len, 0, len, 0, 0, header.cast::<u8>(), LAYOUT::FlagsTy::new_external()), PhantomData);
+            }
+            value.reserve_exact(ADDITIONAL_BUFFER_CAP);
+            return Self::from(value);
+        }
         mem::forget(value);
+        stats::on_adopt(cap);
         // reuse existing buffer
-        let ret = Self(LAYOUT::new_reference(len, cap, 0, 0, 0, ptr, LAYOUT::FlagsTy::new_reference()));
+        let ret = Self(LAYOUT::new_reference(len, cap, len, 0, 0, ptr, LAYOUT::FlagsTy::new_reference()), PhantomData);
         // set ref cnt
-        unsafe { *ret.meta_ptr().cast::<usize>() = 1; }
+        unsafe { Metadata::init(ret.meta_ptr(), 1); }
         ret
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR_OTHER: usize, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const RETAIN_INDICES: bool, const INLINE_SMALL: bool, const STATIC_STORAGE: bool>
-From<BufferMutGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>> for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
-    fn from(value: BufferMutGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>) -> Self {
-        if value.is_inlined() {
-            return Self(value.0.clone());
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<Box<[u8]>> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    #[inline]
+    fn from(value: Box<[u8]>) -> Self {
+        // there is no spare capacity to store our metadata in, so we keep the box alive as the owner of our data
+        Self::from_owner(value)
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<String> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    #[inline]
+    fn from(value: String) -> Self {
+        Self::from(value.into_bytes())
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<&'static str> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    #[inline]
+    fn from(value: &'static str) -> Self {
+        Self::from(value.as_bytes())
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<Cow<'static, [u8]>> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    #[inline]
+    fn from(value: Cow<'static, [u8]>) -> Self {
+        match value {
+            Cow::Borrowed(value) => Self::from(value),
+            Cow::Owned(value) => Self::from(value),
         }
-        let aligned_len = align_unaligned_len_to::<{ align_of::<AtomicUsize>() }>(value.0.ptr_reference(), value.len) + size_of::<AtomicUsize>();
-        debug_assert_eq!((value.0.ptr_reference() as usize + aligned_len) % align_of::<AtomicUsize>(), 0);
-        // reuse the buffer if this instance as we know that we are the only reference to this part of the buffer
-        unsafe { transmute::<BufferMutGeneric<GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL>, BufferGeneric<GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE>>(value) }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR_OTHER: usize, const RETAIN_INDICES_OTHER: bool, const INITIAL_CAP_OTHER: usize, const GROWTH_FACTOR: usize, const RETAIN_INDICES: bool, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool>
-Into<BufferMutGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>> for BufferGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<Arc<[u8]>> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
     #[inline]
-    fn into(self) -> BufferMutGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER> {
-        if self.is_inlined() {
-            return BufferMutGeneric(self.0);
+    fn from(value: Arc<[u8]>) -> Self {
+        Self::from_owner(value)
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherLayout: BufferFormat<INLINE_SMALL, false>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<BufferMutGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    /// Reuses the allocation of the mutable buffer, its indices are carried over according to `RETAIN_INDICES`.
+    /// Inlined data is only copied into an allocation if it doesn't fit into the inline storage of this buffer.
+    #[inline]
+    fn from(mut value: BufferMutGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>) -> Self {
+        // immutable buffers always expect their metadata to be initialized
+        value.share();
+        Self(convert_layout::<INLINE_SMALL, false, STATIC_STORAGE, _, _>(value.into_layout(), ADDITIONAL_BUFFER_CAP), PhantomData).adopt_indices()
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherLayout: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+From<BufferRWGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER>> for BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    /// Reuses the storage of the read-write buffer, its indices are carried over according to `RETAIN_INDICES`.
+    /// Inlined data is only copied into an allocation if it doesn't fit into the inline storage of this buffer.
+    #[inline]
+    fn from(mut value: BufferRWGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER>) -> Self {
+        // immutable buffers always expect their metadata to be initialized
+        value.share();
+        Self(convert_layout::<INLINE_SMALL, STATIC_STORAGE, STATIC_STORAGE, _, _>(value.into_layout(), ADDITIONAL_BUFFER_CAP), PhantomData).adopt_indices()
+    }
+}
+
+/// A weak reference to the data of a buffer which doesn't keep the data alive.
+/// Inlined and static buffers can always be upgraded.
+pub struct WeakBufferGeneric<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE> = FormatPacked, GROWTH: GrowthPolicy = Doubling, const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT, const INLINE_SMALL: bool = true, const STATIC_STORAGE: bool = true, const RETAIN_INDICES: bool = true, const COMPACT_DIVISOR: usize = COMPACT_DIVISOR_DEFAULT> {
+    /// this is null for inlined and static buffers
+    cell: *const WeakCell,
+    layout: LAYOUT,
+    _growth: PhantomData<GROWTH>,
+}
+
+unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Send for WeakBufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> where LAYOUT::RefCntTy: Send {}
+unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Sync for WeakBufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> where LAYOUT::RefCntTy: Sync {}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+WeakBufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+
+    /// Tries to create a buffer from this weak buffer, this fails if all buffers
+    /// referencing the data were dropped already.
+    pub fn upgrade(&self) -> Option<BufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR>> {
+        if self.cell.is_null() {
+            return Some(BufferGeneric(self.layout.clone(), PhantomData));
         }
-        if self.is_static() {
-             // TODO: should we multiply with growth factor?
-            let alloc = unsafe { realloc_buffer_counted(self.0.ptr_reference(), self.0.offset_reference(), self.0.len_reference(), self.0.len_reference() + ADDITIONAL_BUFFER_CAP) };
-            return BufferMutGeneric(LAYOUT::new_reference(self.0.len_reference(), self.0.len_reference() + ADDITIONAL_BUFFER_CAP, self.0.wrx_reference(), self.0.rdx_reference(), self.0.offset_reference(), self.0.ptr_reference(), self.0.flags()));
+        let cell = unsafe { &*self.cell };
+        // while we hold the lock the allocation can't be freed
+        cell.lock();
+        let upgraded = unsafe { cell.is_alive() } && {
+            let meta = unsafe { &*layout_meta(&self.layout) };
+            LAYOUT::RefCntTy::try_increment(&meta.ref_cnt)
+        };
+        cell.unlock();
+        if upgraded {
+            Some(BufferGeneric(self.layout.clone(), PhantomData))
+        } else {
+            None
         }
-        if unsafe { self.is_only() } {
-            let ret = BufferMutGeneric(self.0);
-            mem::forget(self);
-            return ret;
+    }
+
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Clone for WeakBufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    #[inline]
+    fn clone(&self) -> Self {
+        if !self.cell.is_null() {
+            unsafe { &*self.cell }.increment();
+        }
+        Self {
+            cell: self.cell,
+            layout: self.layout.clone(),
+            _growth: PhantomData,
         }
-        let alloc = unsafe { realloc_buffer_counted(self.0.ptr_reference(), self.0.offset_reference(), self.0.len_reference(), self.0.cap_reference()) };
+    }
+}
 
-        BufferMutGeneric(LAYOUT::new_reference(self.0.len_reference(), self.0.cap_reference(), self.0.wrx_reference(), self.0.rdx_reference(), self.0.offset_reference(), self.0.ptr_reference(), self.0.flags()))
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool, const COMPACT_DIVISOR: usize>
+Drop for WeakBufferGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES, COMPACT_DIVISOR> {
+    #[inline]
+    fn drop(&mut self) {
+        if !self.cell.is_null() {
+            unsafe { WeakCell::release(self.cell); }
+        }
     }
 }
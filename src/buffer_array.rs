@@ -0,0 +1,410 @@
+use std::borrow::Borrow;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::{stats, ReadableBuffer, WritableBuffer};
+
+/// A buffer which stores up to `N` bytes in place and never allocates.
+///
+/// The `put_*` methods of `WritableBuffer` panic once the buffer is full,
+/// the `try_put_*` methods return a `CapacityError` instead. As conversions
+/// into the buffer can fail as well, it only implements `TryFrom` and thus
+/// doesn't implement the traits which require `From`, like `GenericBuffer`.
+#[derive(Clone)]
+pub struct BufferArray<const N: usize> {
+    data: [u8; N],
+    rdx: usize,
+    wrx: usize,
+    /// identifies the buffer the data was split from, this is 0 until the buffer gets split.
+    split_id: usize,
+    /// the position of the data within the buffer it was split from.
+    split_offset: usize,
+}
+
+/// The error returned when data doesn't fit into a `BufferArray`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CapacityError {
+    /// the number of bytes the operation required in total.
+    pub required: usize,
+    pub capacity: usize,
+}
+
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "buffer capacity exceeded, required {} bytes but only {} bytes are available", self.required, self.capacity)
+    }
+}
+
+impl Error for CapacityError {}
+
+macro_rules! try_put_ints {
+    ($($name:ident: $ty:ty, $conv:ident;)*) => {
+        $(
+            #[inline]
+            pub fn $name(&mut self, val: $ty) -> Result<(), CapacityError> {
+                self.try_put_slice(&val.$conv())
+            }
+        )*
+    };
+}
+
+/// the source of the ids that tell apart the parts of different splits.
+static NEXT_SPLIT_ID: AtomicUsize = AtomicUsize::new(1);
+
+impl<const N: usize> BufferArray<N> {
+
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            data: [0; N],
+            rdx: 0,
+            wrx: 0,
+            split_id: 0,
+            split_offset: 0,
+        }
+    }
+
+    #[inline]
+    fn check_fits(&self, additional: usize) -> Result<(), CapacityError> {
+        let required = self.wrx + additional;
+        if required > N {
+            return Err(CapacityError { required, capacity: N });
+        }
+        Ok(())
+    }
+
+    /// Creates a buffer containing `val` or fails if it's longer than `N` bytes.
+    #[inline]
+    pub fn try_from_slice(val: &[u8]) -> Result<Self, CapacityError> {
+        let mut ret = Self::new();
+        ret.try_put_slice(val)?;
+        Ok(ret)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.wrx
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.wrx == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.rdx = 0;
+        self.wrx = 0;
+    }
+
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        self.wrx = self.wrx.min(len);
+        self.rdx = self.rdx.min(len);
+    }
+
+    /// Resets the reader index to 0 so the buffer can be read
+    /// from the beginning again.
+    #[inline]
+    pub fn reset_reader_index(&mut self) {
+        self.rdx = 0;
+    }
+
+    /// the buffer has no `RETAIN_INDICES` parameter, so this always resets both indices.
+    #[inline]
+    pub fn reset_writer_index(&mut self) {
+        self.clear();
+    }
+
+    #[inline]
+    pub fn get_slice(&mut self, bytes: usize) -> &[u8] {
+        self.advance(bytes);
+        &self.data[self.rdx - bytes..self.rdx]
+    }
+
+    /// Grows the buffer to `size` bytes by appending zeros or shrinks it to `size` bytes.
+    #[inline]
+    pub fn try_resize(&mut self, size: usize) -> Result<(), CapacityError> {
+        if size > self.wrx {
+            self.try_put_bytes(0, size - self.wrx)
+        } else {
+            self.truncate(size);
+            Ok(())
+        }
+    }
+
+    #[inline]
+    pub fn try_put_slice(&mut self, val: &[u8]) -> Result<(), CapacityError> {
+        self.check_fits(val.len())?;
+        self.data[self.wrx..self.wrx + val.len()].copy_from_slice(val);
+        self.wrx += val.len();
+        Ok(())
+    }
+
+    #[inline]
+    pub fn try_put_bytes(&mut self, val: u8, repeat: usize) -> Result<(), CapacityError> {
+        self.check_fits(repeat)?;
+        self.data[self.wrx..self.wrx + repeat].fill(val);
+        self.wrx += repeat;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn try_put_u8(&mut self, val: u8) -> Result<(), CapacityError> {
+        self.try_put_slice(&[val])
+    }
+
+    #[inline]
+    pub fn try_put_i8(&mut self, val: i8) -> Result<(), CapacityError> {
+        self.try_put_slice(&[val as u8])
+    }
+
+    try_put_ints! {
+        try_put_u16_le: u16, to_le_bytes;
+        try_put_u16_be: u16, to_be_bytes;
+        try_put_u16_ne: u16, to_ne_bytes;
+        try_put_u32_le: u32, to_le_bytes;
+        try_put_u32_be: u32, to_be_bytes;
+        try_put_u32_ne: u32, to_ne_bytes;
+        try_put_u64_le: u64, to_le_bytes;
+        try_put_u64_be: u64, to_be_bytes;
+        try_put_u64_ne: u64, to_ne_bytes;
+        try_put_u128_le: u128, to_le_bytes;
+        try_put_u128_be: u128, to_be_bytes;
+        try_put_u128_ne: u128, to_ne_bytes;
+        try_put_i16_le: i16, to_le_bytes;
+        try_put_i16_be: i16, to_be_bytes;
+        try_put_i16_ne: i16, to_ne_bytes;
+        try_put_i32_le: i32, to_le_bytes;
+        try_put_i32_be: i32, to_be_bytes;
+        try_put_i32_ne: i32, to_ne_bytes;
+        try_put_i64_le: i64, to_le_bytes;
+        try_put_i64_be: i64, to_be_bytes;
+        try_put_i64_ne: i64, to_ne_bytes;
+        try_put_i128_le: i128, to_le_bytes;
+        try_put_i128_be: i128, to_be_bytes;
+        try_put_i128_ne: i128, to_ne_bytes;
+        try_put_f32_le: f32, to_le_bytes;
+        try_put_f32_be: f32, to_be_bytes;
+        try_put_f32_ne: f32, to_ne_bytes;
+        try_put_f64_le: f64, to_le_bytes;
+        try_put_f64_be: f64, to_be_bytes;
+        try_put_f64_ne: f64, to_ne_bytes;
+    }
+
+    /// returns the id of the split this buffer is part of, a new one is assigned on the first split.
+    #[inline]
+    fn split_id(&mut self) -> usize {
+        if self.split_id == 0 {
+            self.split_id = NEXT_SPLIT_ID.fetch_add(1, Ordering::Relaxed);
+        }
+        self.split_id
+    }
+
+    /// Splits the buffer into two at the given offset from the reader index, the returned
+    /// buffer contains the data behind the offset. The data is copied as arrays don't share storage.
+    ///
+    /// #Panic
+    /// Panics when the offset exceeds the remaining bytes.
+    pub fn split_off(&mut self, offset: usize) -> Self {
+        let idx = self.rdx + offset;
+        assert!(self.wrx >= idx, "tried splitting buffer with length {} at {}", self.wrx, idx);
+        let mut other = Self::new();
+        other.data[..self.wrx - idx].copy_from_slice(&self.data[idx..self.wrx]);
+        other.wrx = self.wrx - idx;
+        other.split_id = self.split_id();
+        other.split_offset = self.split_offset + idx;
+        self.wrx = idx;
+        other
+    }
+
+    /// Splits the buffer into two at the given offset from the reader index, the returned
+    /// buffer contains the data in front of the offset. The data is copied as arrays don't share storage.
+    ///
+    /// #Panic
+    /// Panics when the offset exceeds the remaining bytes.
+    pub fn split_to(&mut self, offset: usize) -> Self {
+        let idx = self.rdx + offset;
+        assert!(self.wrx >= idx, "tried splitting buffer with length {} at {}", self.wrx, idx);
+        let mut other = Self::new();
+        other.data[..idx].copy_from_slice(&self.data[..idx]);
+        other.wrx = idx;
+        other.rdx = self.rdx;
+        other.split_id = self.split_id();
+        other.split_offset = self.split_offset;
+        // move the remaining data to the front of the storage
+        self.data.copy_within(idx..self.wrx, 0);
+        self.wrx -= idx;
+        self.rdx = 0;
+        self.split_offset += idx;
+        other
+    }
+
+    #[inline]
+    pub fn split(&mut self) -> Self {
+        self.split_off(0)
+    }
+
+    /// #Panic
+    /// Panics when the buffers can't be merged, see `try_unsplit`.
+    pub fn unsplit(&mut self, other: Self) {
+        if let Err(other) = self.try_unsplit(other) {
+            panic!("tried merging buffers of {} and {} bytes which weren't adjacent parts of a split or don't fit into {} bytes", self.wrx, other.wrx, N);
+        }
+    }
+
+    /// Merges this buffer with `other` if they are adjacent parts of an earlier split,
+    /// which haven't been modified in a way that changed where their data ends.
+    /// The data is copied into this buffer, so the combined data has to fit into `N` bytes.
+    ///
+    /// The combined buffer keeps the reader index of the front part. It continues in the
+    /// back part if the front part was read completely, if the front part still has unread
+    /// data the back part mustn't have been read from yet.
+    pub fn try_unsplit(&mut self, other: Self) -> Result<(), Self> {
+        if self.split_id == 0 || self.split_id != other.split_id {
+            return Err(other);
+        }
+        let self_front = self.split_offset + self.wrx == other.split_offset;
+        let (front, back) = if self_front {
+            (&*self, &other)
+        } else if other.split_offset + other.wrx == self.split_offset {
+            (&other, &*self)
+        } else {
+            return Err(other);
+        };
+        if front.rdx < front.wrx && back.rdx > 0 {
+            return Err(other);
+        }
+        if self.check_fits(other.wrx).is_err() {
+            return Err(other);
+        }
+        let rdx = if front.rdx < front.wrx { front.rdx } else { front.wrx + back.rdx };
+        let (front_len, back_len) = (front.wrx, back.wrx);
+        if !self_front {
+            // make room for the front part's data
+            self.data.copy_within(..back_len, front_len);
+            self.data[..front_len].copy_from_slice(&other.data[..front_len]);
+            self.split_offset = other.split_offset;
+        } else {
+            self.data[front_len..front_len + back_len].copy_from_slice(&other.data[..back_len]);
+        }
+        self.wrx = front_len + back_len;
+        self.rdx = rdx;
+        Ok(())
+    }
+
+}
+
+impl<const N: usize> ReadableBuffer for BufferArray<N> {
+
+    #[inline]
+    fn advance(&mut self, amount: usize) {
+        assert!(amount <= self.remaining(), "not enough bytes in buffer, expected {} readable bytes but only {} bytes are left", amount, self.remaining());
+        self.rdx += amount;
+    }
+
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.wrx - self.rdx
+    }
+
+    #[inline]
+    fn get_u8(&mut self) -> u8 {
+        self.get_slice(1)[0]
+    }
+
+    #[inline]
+    fn copy_to_slice(&mut self, dst: &mut [u8]) {
+        dst.copy_from_slice(self.get_slice(dst.len()));
+    }
+
+}
+
+impl<const N: usize> WritableBuffer for BufferArray<N> {
+
+    /// #Panic
+    /// Panics when the buffer can't hold `repeat` additional bytes, see `try_put_bytes`.
+    #[inline]
+    fn put_bytes(&mut self, val: u8, repeat: usize) {
+        if let Err(err) = self.try_put_bytes(val, repeat) {
+            panic!("{}", err);
+        }
+    }
+
+    /// #Panic
+    /// Panics when the buffer can't hold `val`, see `try_put_slice`.
+    #[inline]
+    fn put_slice(&mut self, val: &[u8]) {
+        if let Err(err) = self.try_put_slice(val) {
+            panic!("{}", err);
+        }
+    }
+
+    #[inline]
+    fn put_u8(&mut self, val: u8) {
+        self.put_slice(&[val]);
+    }
+
+}
+
+impl<const N: usize> AsRef<[u8]> for BufferArray<N> {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        &self.data[self.rdx..self.wrx]
+    }
+}
+
+impl<const N: usize> Deref for BufferArray<N> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.as_ref()
+    }
+}
+
+impl<const N: usize> Borrow<[u8]> for BufferArray<N> {
+    #[inline]
+    fn borrow(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
+
+impl<const N: usize> Default for BufferArray<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TryFrom<&[u8]> for BufferArray<N> {
+    type Error = CapacityError;
+
+    #[inline]
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Self::try_from_slice(value)
+    }
+}
+
+impl<const N: usize> TryFrom<Vec<u8>> for BufferArray<N> {
+    type Error = CapacityError;
+
+    #[inline]
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::try_from_slice(&value)
+    }
+}
+
+impl<const N: usize> From<BufferArray<N>> for Vec<u8> {
+    #[inline]
+    fn from(value: BufferArray<N>) -> Self {
+        stats::on_conversion_copy();
+        value.data[..value.wrx].to_vec()
+    }
+}
@@ -1,23 +1,22 @@
-use crate::{buffer_layout::{BaseBuffer, INLINE_SIZE_BITS, BufferUnion, ReferenceBuffer}, util::{build_bit_mask, round_up_pow_2, greater_zero_ret_one}};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ptr;
 
-use super::{Flags, BufferFormat};
+use crate::{buffer_layout::{LenWord, BaseBuffer, INLINE_WORDS_DEFAULT, inline_size_bits, BufferUnion, ReferenceBuffer}, util::{build_bit_mask, round_up_pow_2, greater_zero_ret_one}};
+
+use crate::ref_cnt::{AtomicRefCnt, RefCnt};
 
-const INLINE_LEN_MASK: usize = build_bit_mask(0, INLINE_SIZE_BITS);
-const INLINE_OFFSET_MASK: usize = build_bit_mask(INLINE_OFFSET_SHIFT, INLINE_SIZE_BITS);
-const INLINE_OFFSET_SHIFT: usize = INLINE_SIZE_BITS;
-const INLINE_WRX_MASK: usize = build_bit_mask(INLINE_WRX_SHIFT, INLINE_SIZE_BITS);
-const INLINE_WRX_SHIFT: usize = INLINE_SIZE_BITS * 2;
-const INLINE_RDX_MASK: usize = build_bit_mask(INLINE_RDX_SHIFT, INLINE_SIZE_BITS);
-const INLINE_RDX_SHIFT: usize = INLINE_SIZE_BITS * 3;
+use super::{Flags, BufferFormat};
 
 const LEN_MASK: usize = build_bit_mask(LEN_SHIFT, COMPRESSED_WORD_SIZE);
 const LEN_SHIFT: usize = 0;
 
-const RDX_UPPER_MASK: usize = build_bit_mask(RDX_UPPER_SHIFT, usize::BITS as usize / 4 * 1);
+const RDX_UPPER_MASK: usize = build_bit_mask(RDX_UPPER_SHIFT, usize::BITS as usize / 4);
 const RDX_UPPER_SHIFT: usize = CAP_OFFSET_SHIFT + CAP_OFFSET_BITS;
 
-const RDX_LOWER_MASK: usize = build_bit_mask(usize::BITS as usize / 8 * 5, usize::BITS as usize / 8 * 3);
-const RDX_LOWER_SHIFT: usize = RDX_LOWER_MASK.trailing_zeros() as usize;
+const RDX_LOWER_MASK: usize = build_bit_mask(RDX_LOWER_SHIFT, RDX_LOWER_BITS);
+const RDX_LOWER_SHIFT: usize = COMPRESSED_WORD_SIZE;
+const RDX_LOWER_BITS: usize = TAIL_SPACE;
 
 const WRX_MASK: usize = build_bit_mask(WRX_SHIFT, COMPRESSED_WORD_SIZE);
 const WRX_SHIFT: usize = 0;
@@ -35,20 +34,23 @@ const CAP_SHIFT: usize = TAIL_SHIFT;
 pub(crate) const COMPRESSED_WORD_SIZE: usize = usize::BITS as usize / 8 * 5;
 pub(crate) const TAIL_SPACE: usize = usize::BITS as usize - COMPRESSED_WORD_SIZE;
 
-pub(crate) const WORD_MASK: usize = build_bit_mask(0, COMPRESSED_WORD_SIZE);
 pub(crate) const TAIL_MASK: usize = build_bit_mask(COMPRESSED_WORD_SIZE, TAIL_SPACE);
 pub(crate) const TAIL_SHIFT: usize = COMPRESSED_WORD_SIZE;
 
-// returns a pair of capacity and capacity_offset
+// returns a pair of capacity and capacity_offset, capacities that don't fit into
+// the `TAIL_SPACE` bits are rounded up to the next representable value.
 #[inline]
 pub(crate) const fn translate_cap(capacity: usize) -> (usize, usize) {
-    let req_bit = round_up_pow_2(capacity).leading_zeros() as usize;
-    let offset = req_bit.saturating_sub(TAIL_SPACE);
-    let clear_offset = usize::BITS as usize - offset;
-    // get the lower bits that normally get cleared.
-    let lower_bits = capacity << clear_offset;
-    let rounded_cap = greater_zero_ret_one(lower_bits) << (clear_offset + 1);
-    ((capacity >> offset) + rounded_cap, offset)
+    let req_bits = (usize::BITS - capacity.leading_zeros()) as usize;
+    let offset = req_bits.saturating_sub(TAIL_SPACE);
+    // round up if any of the lower bits get cleared.
+    let cap = (capacity >> offset) + greater_zero_ret_one(capacity & build_bit_mask(0, offset));
+    if cap >> TAIL_SPACE != 0 {
+        // rounding up carried into the next bit
+        (cap >> 1, offset + 1)
+    } else {
+        (cap, offset)
+    }
 }
 
 // representation on 64 bit systems:
@@ -64,148 +66,184 @@ pub(crate) const fn translate_cap(capacity: usize) -> (usize, usize) {
 // capacity shift: 4
 // 30 - 24 - 4 = 2
 //
-// -> 2 bits remaining!
+// -> 2 bits remaining, one of which is used by the unique flag!
 //
-// 1. word: len[40 bits], cap_offset [4 bits], rdx_upper[16 bits], unused[2 bits], flags[2 bits]
+// 1. word: len[40 bits], cap_offset [4 bits], rdx_upper[16 bits], unused[1 bit], unique[1 bit], flags[2 bits]
 // 2. word: wrx[40 bits], rdx_lower[24 bits]
 // 3. word: offset[40 bits], capacity[24 bits]
 
 /// This format is slower but allows for a maximum capacity of `size_of(usize) / 8 * 5`.
 #[derive(Clone)]
-pub struct FormatExtended(BaseBuffer);
+pub struct FormatExtended<RC: RefCnt = AtomicRefCnt, const INLINE_WORDS: usize = INLINE_WORDS_DEFAULT>(BaseBuffer<INLINE_WORDS>, PhantomData<RC>);
+
+impl<RC: RefCnt, const INLINE_WORDS: usize> FormatExtended<RC, INLINE_WORDS> {
+    const INLINE_SIZE_BITS: usize = inline_size_bits(INLINE_WORDS);
+    const INLINE_LEN_MASK: usize = build_bit_mask(0, Self::INLINE_SIZE_BITS);
+    const INLINE_OFFSET_MASK: usize = build_bit_mask(Self::INLINE_OFFSET_SHIFT, Self::INLINE_SIZE_BITS);
+    const INLINE_OFFSET_SHIFT: usize = Self::INLINE_SIZE_BITS;
+    const INLINE_WRX_MASK: usize = build_bit_mask(Self::INLINE_WRX_SHIFT, Self::INLINE_SIZE_BITS);
+    const INLINE_WRX_SHIFT: usize = Self::INLINE_SIZE_BITS * 2;
+    const INLINE_RDX_MASK: usize = build_bit_mask(Self::INLINE_RDX_SHIFT, Self::INLINE_SIZE_BITS);
+    const INLINE_RDX_SHIFT: usize = Self::INLINE_SIZE_BITS * 3;
+    /// the 4 inline indices have to fit into the first word next to the flags.
+    const INLINE_INDICES_FIT: () = assert!(Self::INLINE_SIZE_BITS * 4 <= usize::BITS as usize - 3, "too many inline words");
+}
 
-impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE_SUPPORT, STATIC_SUPPORT> for FormatExtended {
+impl<RC: RefCnt, const INLINE_WORDS: usize, const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE_SUPPORT, STATIC_SUPPORT> for FormatExtended<RC, INLINE_WORDS> {
     type FlagsTy = BufferTy;
+    type RefCntTy = RC;
+
+    const INLINE_SIZE: usize = INLINE_WORDS * size_of::<usize>();
+    const MAX_REFERENCE_SIZE: usize = build_bit_mask(0, COMPRESSED_WORD_SIZE);
 
     #[inline]
     fn new_reference(len: usize, cap: usize, wrx: usize, rdx: usize, offset: usize, ptr: *mut u8, flags: Self::FlagsTy) -> Self {
-        let (cap, cap_offset) = translate_cap(cap);
-        let len = len | flags.0 | (cap_offset << CAP_OFFSET_SHIFT);
-        Self(BaseBuffer {
-            len,
-            buffer: BufferUnion { reference: ReferenceBuffer::new(cap, offset, wrx, ptr), },
-        })
+        let mut ret = Self(BaseBuffer {
+            len: LenWord::new((len << LEN_SHIFT) | flags.0),
+            buffer: BufferUnion {
+                reference: ReferenceBuffer {
+                    wrx: wrx << WRX_SHIFT,
+                    offset: offset << OFFSET_SHIFT,
+                    ptr,
+                },
+            },
+        }, PhantomData);
+        BufferFormat::<INLINE_SUPPORT, STATIC_SUPPORT>::set_rdx_reference(&mut ret, rdx);
+        BufferFormat::<INLINE_SUPPORT, STATIC_SUPPORT>::set_cap_reference(&mut ret, cap);
+        ret
     }
 
     #[inline]
-    fn new_inlined(len: usize, offset: usize, value: [usize; 3]) -> Self {
+    fn new_inlined(len: usize, offset: usize, value: &[u8]) -> Self {
+        let () = Self::INLINE_INDICES_FIT;
+        debug_assert!(value.len() <= INLINE_WORDS * size_of::<usize>());
+        let mut inlined = [0; INLINE_WORDS];
+        unsafe { ptr::copy_nonoverlapping(value.as_ptr(), inlined.as_mut_ptr().cast::<u8>(), value.len()); }
         Self(BaseBuffer {
-            len: len | (offset << INLINE_OFFSET_SHIFT),
-            buffer: BufferUnion { inlined: [0; 3], },
-        })
+            len: LenWord::new(len | (offset << Self::INLINE_OFFSET_SHIFT) | INLINE_BUFFER_FLAG),
+            buffer: BufferUnion { inlined },
+        }, PhantomData)
     }
 
     #[inline]
     fn len_reference(&self) -> usize {
-        (self.0.len & LEN_MASK) >> LEN_SHIFT
+        (self.0.len.get() & LEN_MASK) >> LEN_SHIFT
     }
 
     #[inline]
     fn len_inlined(&self) -> usize {
-        self.0.len & INLINE_LEN_MASK
+        self.0.len.get() & Self::INLINE_LEN_MASK
     }
 
     #[inline]
     fn set_len_reference(&mut self, len: usize) {
-        self.0.len = (self.0.len & !LEN_MASK) | (len << LEN_SHIFT);
+        self.0.len.set((self.0.len.get() & !LEN_MASK) | (len << LEN_SHIFT));
     }
 
     #[inline]
     fn set_len_inlined(&mut self, len: usize) {
-        self.0.len = (self.0.len & !INLINE_LEN_MASK) | len;
+        self.0.len.set((self.0.len.get() & !Self::INLINE_LEN_MASK) | len);
     }
 
     #[inline]
     fn offset_reference(&self) -> usize {
-        (self.0.buffer.reference.offset & OFFSET_MASK) << OFFSET_SHIFT
+        (unsafe { self.0.buffer.reference.offset } & OFFSET_MASK) >> OFFSET_SHIFT
     }
 
     #[inline]
     fn offset_inlined(&self) -> usize {
-        (self.0.len & INLINE_OFFSET_MASK) >> INLINE_OFFSET_SHIFT
+        (self.0.len.get() & Self::INLINE_OFFSET_MASK) >> Self::INLINE_OFFSET_SHIFT
     }
 
     #[inline]
     fn set_offset_reference(&mut self, offset: usize) {
-        self.0.buffer.reference.offset = (self.0.buffer.reference.offset & !OFFSET_MASK) | (offset << OFFSET_SHIFT);
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.offset = (reference.offset & !OFFSET_MASK) | (offset << OFFSET_SHIFT);
     }
 
     #[inline]
     fn set_offset_inlined(&mut self, offset: usize) {
-        self.0.len = (self.0.len & !INLINE_OFFSET_MASK) | (offset << INLINE_OFFSET_SHIFT);
+        self.0.len.set((self.0.len.get() & !Self::INLINE_OFFSET_MASK) | (offset << Self::INLINE_OFFSET_SHIFT));
     }
     
     #[inline]
     fn wrx_reference(&self) -> usize {
-        (self.0.buffer.reference.wrx & WRX_MASK) >> WRX_SHIFT
+        (unsafe { self.0.buffer.reference.wrx } & WRX_MASK) >> WRX_SHIFT
     }
 
     #[inline]
     fn wrx_inlined(&self) -> usize {
-        (self.0.len & INLINE_WRX_MASK) >> INLINE_WRX_SHIFT
+        (self.0.len.get() & Self::INLINE_WRX_MASK) >> Self::INLINE_WRX_SHIFT
     }
 
     #[inline]
     fn set_wrx_reference(&mut self, wrx: usize) {
-        self.0.buffer.reference.wrx = (self.0.buffer.reference.wrx & !WRX_MASK) | (wrx << WRX_SHIFT);
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.wrx = (reference.wrx & !WRX_MASK) | (wrx << WRX_SHIFT);
     }
 
     #[inline]
     fn set_wrx_inlined(&mut self, wrx: usize) {
-        self.0.len = (self.0.len & !INLINE_WRX_MASK) | (wrx << INLINE_WRX_SHIFT);
+        self.0.len.set((self.0.len.get() & !Self::INLINE_WRX_MASK) | (wrx << Self::INLINE_WRX_SHIFT));
     }
 
     #[inline]
     fn rdx_reference(&self) -> usize {
-        ((self.0.buffer.reference.wrx & RDX_LOWER_MASK) >> RDX_LOWER_SHIFT) | ((self.0.len & RDX_UPPER_MASK) >> (RDX_UPPER_SHIFT - RDX_LOWER_SHIFT))
+        let lower = (unsafe { self.0.buffer.reference.wrx } & RDX_LOWER_MASK) >> RDX_LOWER_SHIFT;
+        let upper = (self.0.len.get() & RDX_UPPER_MASK) >> RDX_UPPER_SHIFT;
+        lower | (upper << RDX_LOWER_BITS)
     }
 
     #[inline]
     fn rdx_inlined(&self) -> usize {
-        (self.0.len & INLINE_RDX_MASK) >> INLINE_RDX_SHIFT
+        (self.0.len.get() & Self::INLINE_RDX_MASK) >> Self::INLINE_RDX_SHIFT
     }
 
     #[inline]
     fn set_rdx_reference(&mut self, rdx: usize) {
-        self.0.buffer.reference.wrx = (self.0.buffer.reference.wrx & !RDX_LOWER_MASK) | (rdx << RDX_LOWER_SHIFT);
-        self.0.len = (self.0.len & !RDX_UPPER_MASK) | ((rdx << (RDX_UPPER_SHIFT - RDX_LOWER_SHIFT)) & RDX_UPPER_MASK);
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.wrx = (reference.wrx & !RDX_LOWER_MASK) | ((rdx << RDX_LOWER_SHIFT) & RDX_LOWER_MASK);
+        self.0.len.set((self.0.len.get() & !RDX_UPPER_MASK) | (((rdx >> RDX_LOWER_BITS) << RDX_UPPER_SHIFT) & RDX_UPPER_MASK));
     }
 
     #[inline]
     fn set_rdx_inlined(&mut self, rdx: usize) {
-        self.0.len = (self.0.len & !INLINE_RDX_MASK) | (rdx << INLINE_RDX_SHIFT);
+        self.0.len.set((self.0.len.get() & !Self::INLINE_RDX_MASK) | (rdx << Self::INLINE_RDX_SHIFT));
     }
 
     #[inline]
     fn cap_reference(&self) -> usize {
-        let raw = (self.0.buffer.reference.offset & CAP_MASK) >> CAP_SHIFT;
-        let shift = (self.0.len & CAP_OFFSET_MASK) >> CAP_OFFSET_SHIFT;
+        let raw = (unsafe { self.0.buffer.reference.offset } & CAP_MASK) >> CAP_SHIFT;
+        let shift = (self.0.len.get() & CAP_OFFSET_MASK) >> CAP_OFFSET_SHIFT;
         raw << shift
     }
 
+    #[inline]
     fn cap_inlined(&self) -> usize {
-        todo!()
+        INLINE_WORDS * size_of::<usize>()
     }
 
     #[inline]
     fn set_cap_reference(&mut self, cap: usize) {
         let (cap, cap_offset) = translate_cap(cap);
-        self.0.buffer.reference.offset = (self.0.buffer.reference.offset & !CAP_MASK) | (cap << CAP_SHIFT);
-        self.0.len = (self.0.len & !CAP_OFFSET_MASK) | (cap_offset << CAP_OFFSET_SHIFT);
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.offset = (reference.offset & !CAP_MASK) | (cap << CAP_SHIFT);
+        self.0.len.set((self.0.len.get() & !CAP_OFFSET_MASK) | (cap_offset << CAP_OFFSET_SHIFT));
     }
 
-    fn set_cap_inlined(&mut self, cap: usize) {
-        todo!()
+    fn set_cap_inlined(&mut self, _cap: usize) {
+        // the inline storage has a fixed size
+        unreachable!();
     }
 
     #[inline]
     fn ptr_reference(&self) -> *mut u8 {
-        self.0.buffer.reference.ptr
+        unsafe { self.0.buffer.reference.ptr }
     }
 
     #[inline]
     fn ptr_inlined(&self) -> *mut u8 {
-        (&self.0.buffer.inlined as *const [usize; 3]).cast::<u8>().cast_mut()
+        ptr::addr_of!(self.0.buffer.inlined).cast::<u8>().cast_mut()
     }
 
     #[inline]
@@ -215,18 +253,28 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
 
     #[inline]
     fn flags(&self) -> Self::FlagsTy {
-        BufferTy(self.0.len & BUFFER_TY_MASK)
+        BufferTy(self.0.len.get() & FLAGS_MASK)
+    }
+
+    #[inline]
+    fn mark_shared(&self) {
+        self.0.len.clear_shared(UNIQUE_BUFFER_FLAG);
     }
 }
 
 const INLINE_BUFFER_FLAG: usize = 1 << (usize::BITS - 1);
 const STATIC_BUFFER_FLAG: usize = 1 << (usize::BITS - 2);
+/// external buffers use the last remaining combination of the flag bits
+const EXTERNAL_BUFFER_FLAG: usize = INLINE_BUFFER_FLAG | STATIC_BUFFER_FLAG;
+/// unique buffers use one of the unused bits right below the buffer type bits
+const UNIQUE_BUFFER_FLAG: usize = 1 << (usize::BITS - 3);
 
 /// the last 2 bits will never be used as allocations are capped at usize::MAX / 8 * 5
 const BUFFER_TY_MASK: usize = build_bit_mask(usize::BITS as usize - 2, 2);
+const FLAGS_MASK: usize = BUFFER_TY_MASK | UNIQUE_BUFFER_FLAG;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-struct BufferTy(usize);
+pub struct BufferTy(usize);
 
 impl Flags for BufferTy {
     #[inline]
@@ -244,6 +292,11 @@ impl Flags for BufferTy {
         Self(0)
     }
 
+    #[inline]
+    fn new_external() -> Self {
+        Self(EXTERNAL_BUFFER_FLAG)
+    }
+
     #[inline]
     fn is_inlined(self) -> bool {
         self.0 == INLINE_BUFFER_FLAG
@@ -256,6 +309,21 @@ impl Flags for BufferTy {
 
     #[inline]
     fn is_reference(self) -> bool {
-        self.0 == 0
+        self.0 & BUFFER_TY_MASK == 0
+    }
+
+    #[inline]
+    fn is_external(self) -> bool {
+        self.0 == EXTERNAL_BUFFER_FLAG
+    }
+
+    #[inline]
+    fn new_unique() -> Self {
+        Self(UNIQUE_BUFFER_FLAG)
+    }
+
+    #[inline]
+    fn is_unique(self) -> bool {
+        self.0 & UNIQUE_BUFFER_FLAG != 0
     }
 }
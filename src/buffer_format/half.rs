@@ -1,29 +1,30 @@
-use crate::{buffer_layout::{BaseBuffer, INLINE_SIZE_BITS, BufferUnion, ReferenceBuffer}, util::build_bit_mask};
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ptr;
 
-use super::{BufferFormat, Flags, extended::translate_cap};
+use crate::{buffer_layout::{LenWord, BaseBuffer, INLINE_WORDS_DEFAULT, inline_size_bits, BufferUnion, ReferenceBuffer}, util::build_bit_mask};
 
-const INLINE_LEN_MASK: usize = build_bit_mask(0, INLINE_SIZE_BITS);
-const INLINE_OFFSET_MASK: usize = build_bit_mask(INLINE_OFFSET_SHIFT, INLINE_SIZE_BITS);
-const INLINE_OFFSET_SHIFT: usize = INLINE_SIZE_BITS;
-const INLINE_WRX_MASK: usize = build_bit_mask(INLINE_WRX_SHIFT, INLINE_SIZE_BITS);
-const INLINE_WRX_SHIFT: usize = INLINE_SIZE_BITS * 2;
-const INLINE_RDX_MASK: usize = build_bit_mask(INLINE_RDX_SHIFT, INLINE_SIZE_BITS);
-const INLINE_RDX_SHIFT: usize = INLINE_SIZE_BITS * 3;
+use crate::ref_cnt::{AtomicRefCnt, RefCnt};
 
-const LEN_MASK: usize = build_bit_mask(LEN_SHIFT, usize::BITS as usize / 2);
-const LEN_SHIFT: usize = 0;
+use super::{BufferFormat, Flags};
 
-const RDX_MASK: usize = build_bit_mask(RDX_SHIFT, usize::BITS as usize / 2);
-const RDX_SHIFT: usize = usize::BITS as usize / 2;
+pub(crate) const LEN_MASK: usize = build_bit_mask(LEN_SHIFT, usize::BITS as usize / 2);
+pub(crate) const LEN_SHIFT: usize = 0;
 
-const WRX_MASK: usize = build_bit_mask(WRX_SHIFT, usize::BITS as usize / 2);
-const WRX_SHIFT: usize = 0;
+pub(crate) const RDX_MASK: usize = build_bit_mask(RDX_SHIFT, usize::BITS as usize / 2);
+pub(crate) const RDX_SHIFT: usize = usize::BITS as usize / 2;
 
-const CAP_MASK: usize = build_bit_mask(CAP_SHIFT, usize::BITS as usize / 2);
-const CAP_SHIFT: usize = usize::BITS as usize / 2;
+pub(crate) const WRX_MASK: usize = build_bit_mask(WRX_SHIFT, usize::BITS as usize / 2);
+pub(crate) const WRX_SHIFT: usize = 0;
 
-const OFFSET_MASK: usize = build_bit_mask(OFFSET_SHIFT, usize::BITS as usize / 2);
-const OFFSET_SHIFT: usize = 0;
+pub(crate) const CAP_MASK: usize = build_bit_mask(CAP_SHIFT, usize::BITS as usize / 2);
+pub(crate) const CAP_SHIFT: usize = usize::BITS as usize / 2;
+
+pub(crate) const OFFSET_MASK: usize = build_bit_mask(OFFSET_SHIFT, usize::BITS as usize / 2);
+pub(crate) const OFFSET_SHIFT: usize = 0;
+
+/// every value of outlined buffers is stored in half a word.
+pub(crate) const MAX_REFERENCE_SIZE: usize = build_bit_mask(0, usize::BITS as usize / 2);
 
 // representation on 64 bit systems:
 //
@@ -37,43 +38,70 @@ const OFFSET_SHIFT: usize = 0;
 // capacity: 32
 // 62 - 32 = 30
 //
-// -> 30 bits remaining!
+// -> 30 bits remaining, one of which is used by the unique flag!
 //
-// 1. word: len[32 bits], unused[30 bits], flags[2 bits]
+// 1. word: len[32 bits], unused[29 bits], unique[1 bit], flags[2 bits]
 // 2. word: wrx[32 bits], rdx[32 bits]
 // 3. word: offset[32 bits], capacity[32 bits]
 
 /// This format is faster but only allows for a maximum capacity of `size_of(usize) / 2`.
 #[derive(Clone)]
-pub struct FormatHalf(BaseBuffer);
+pub struct FormatHalf<RC: RefCnt = AtomicRefCnt, const INLINE_WORDS: usize = INLINE_WORDS_DEFAULT>(BaseBuffer<INLINE_WORDS>, PhantomData<RC>);
+
+impl<RC: RefCnt, const INLINE_WORDS: usize> FormatHalf<RC, INLINE_WORDS> {
+    const INLINE_SIZE_BITS: usize = inline_size_bits(INLINE_WORDS);
+    const INLINE_LEN_MASK: usize = build_bit_mask(0, Self::INLINE_SIZE_BITS);
+    const INLINE_OFFSET_MASK: usize = build_bit_mask(Self::INLINE_OFFSET_SHIFT, Self::INLINE_SIZE_BITS);
+    const INLINE_OFFSET_SHIFT: usize = Self::INLINE_SIZE_BITS;
+    const INLINE_WRX_MASK: usize = build_bit_mask(Self::INLINE_WRX_SHIFT, Self::INLINE_SIZE_BITS);
+    const INLINE_WRX_SHIFT: usize = Self::INLINE_SIZE_BITS * 2;
+    const INLINE_RDX_MASK: usize = build_bit_mask(Self::INLINE_RDX_SHIFT, Self::INLINE_SIZE_BITS);
+    const INLINE_RDX_SHIFT: usize = Self::INLINE_SIZE_BITS * 3;
+    /// the 4 inline indices have to fit into the first word next to the flags.
+    const INLINE_INDICES_FIT: () = assert!(Self::INLINE_SIZE_BITS * 4 <= usize::BITS as usize - 3, "too many inline words");
+}
 
-impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE_SUPPORT, STATIC_SUPPORT> for FormatHalf {
+impl<RC: RefCnt, const INLINE_WORDS: usize, const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE_SUPPORT, STATIC_SUPPORT> for FormatHalf<RC, INLINE_WORDS> {
     type FlagsTy = BufferTy<INLINE_SUPPORT, STATIC_SUPPORT>;
+    type RefCntTy = RC;
+
+    const INLINE_SIZE: usize = INLINE_WORDS * size_of::<usize>();
+    const MAX_REFERENCE_SIZE: usize = MAX_REFERENCE_SIZE;
 
+    /// unlike in `FormatExtended` the capacity is stored exactly, as every value gets half a word.
     #[inline]
     fn new_reference(len: usize, cap: usize, wrx: usize, rdx: usize, offset: usize, ptr: *mut u8, flags: Self::FlagsTy) -> Self {
-        let (cap, cap_offset) = translate_cap(cap);
-        let len = len | flags.0;
+        debug_assert!(len.max(cap).max(wrx).max(offset) <= MAX_REFERENCE_SIZE);
         Self(BaseBuffer {
-            len,
-            buffer: BufferUnion { reference: ReferenceBuffer::new(cap, offset, wrx, ptr), },
-        })
+            len: LenWord::new((len << LEN_SHIFT) | flags.0),
+            buffer: BufferUnion {
+                reference: ReferenceBuffer {
+                    wrx: (wrx << WRX_SHIFT) | (rdx << RDX_SHIFT),
+                    offset: (offset << OFFSET_SHIFT) | (cap << CAP_SHIFT),
+                    ptr,
+                },
+            },
+        }, PhantomData)
     }
 
     #[inline]
-    fn new_inlined(len: usize, offset: usize, value: [usize; 3]) -> Self {
+    fn new_inlined(len: usize, offset: usize, value: &[u8]) -> Self {
         if !INLINE_SUPPORT {
             unreachable!();
         }
+        let () = Self::INLINE_INDICES_FIT;
+        debug_assert!(value.len() <= INLINE_WORDS * size_of::<usize>());
+        let mut inlined = [0; INLINE_WORDS];
+        unsafe { ptr::copy_nonoverlapping(value.as_ptr(), inlined.as_mut_ptr().cast::<u8>(), value.len()); }
         Self(BaseBuffer {
-            len: len | (offset << INLINE_OFFSET_SHIFT),
-            buffer: BufferUnion { inlined: [0; 3], },
-        })
+            len: LenWord::new(len | (offset << Self::INLINE_OFFSET_SHIFT) | INLINE_BUFFER_FLAG),
+            buffer: BufferUnion { inlined },
+        }, PhantomData)
     }
 
     #[inline]
     fn len_reference(&self) -> usize {
-        self.0.len & LEN_MASK
+        self.0.len.get() & LEN_MASK
     }
 
     #[inline]
@@ -81,12 +109,12 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        self.0.len & INLINE_LEN_MASK
+        self.0.len.get() & Self::INLINE_LEN_MASK
     }
 
     #[inline]
     fn set_len_reference(&mut self, len: usize) {
-        self.0.len = (self.0.len & !LEN_MASK) | (len << LEN_SHIFT);
+        self.0.len.set((self.0.len.get() & !LEN_MASK) | (len << LEN_SHIFT));
     }
 
     #[inline]
@@ -94,12 +122,12 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        self.0.len = (self.0.len & !INLINE_LEN_MASK) | len;
+        self.0.len.set((self.0.len.get() & !Self::INLINE_LEN_MASK) | len);
     }
 
     #[inline]
     fn offset_reference(&self) -> usize {
-        (self.0.buffer.reference.offset & OFFSET_MASK) >> OFFSET_SHIFT
+        (unsafe { self.0.buffer.reference.offset } & OFFSET_MASK) >> OFFSET_SHIFT
     }
 
     #[inline]
@@ -107,12 +135,13 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        (self.0.len & INLINE_OFFSET_MASK) >> INLINE_OFFSET_SHIFT
+        (self.0.len.get() & Self::INLINE_OFFSET_MASK) >> Self::INLINE_OFFSET_SHIFT
     }
 
     #[inline]
     fn set_offset_reference(&mut self, offset: usize) {
-        self.0.buffer.reference.offset = (self.0.buffer.reference.offset & !OFFSET_MASK) | (offset << OFFSET_SHIFT);
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.offset = (reference.offset & !OFFSET_MASK) | (offset << OFFSET_SHIFT);
     }
 
     #[inline]
@@ -120,12 +149,12 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        self.0.len = (self.0.len & !INLINE_OFFSET_MASK) | (offset << INLINE_OFFSET_SHIFT);
+        self.0.len.set((self.0.len.get() & !Self::INLINE_OFFSET_MASK) | (offset << Self::INLINE_OFFSET_SHIFT));
     }
     
     #[inline]
     fn wrx_reference(&self) -> usize {
-        (self.0.buffer.reference.wrx & WRX_MASK) >> WRX_SHIFT
+        (unsafe { self.0.buffer.reference.wrx } & WRX_MASK) >> WRX_SHIFT
     }
 
     #[inline]
@@ -133,12 +162,13 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        (self.0.len & INLINE_WRX_MASK) >> INLINE_WRX_SHIFT
+        (self.0.len.get() & Self::INLINE_WRX_MASK) >> Self::INLINE_WRX_SHIFT
     }
 
     #[inline]
     fn set_wrx_reference(&mut self, wrx: usize) {
-        self.0.buffer.reference.wrx = (self.0.buffer.reference.wrx & !WRX_MASK) | (wrx << WRX_SHIFT);
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.wrx = (reference.wrx & !WRX_MASK) | (wrx << WRX_SHIFT);
     }
 
     #[inline]
@@ -146,12 +176,12 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        self.0.len = (self.0.len & !INLINE_WRX_MASK) | (wrx << INLINE_WRX_SHIFT);
+        self.0.len.set((self.0.len.get() & !Self::INLINE_WRX_MASK) | (wrx << Self::INLINE_WRX_SHIFT));
     }
 
     #[inline]
     fn rdx_reference(&self) -> usize {
-        (self.0.buffer.reference.wrx & RDX_MASK) >> RDX_SHIFT
+        (unsafe { self.0.buffer.reference.wrx } & RDX_MASK) >> RDX_SHIFT
     }
 
     #[inline]
@@ -159,12 +189,13 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        (self.0.len & INLINE_RDX_MASK) >> INLINE_RDX_SHIFT
+        (self.0.len.get() & Self::INLINE_RDX_MASK) >> Self::INLINE_RDX_SHIFT
     }
 
     #[inline]
     fn set_rdx_reference(&mut self, rdx: usize) {
-        self.0.buffer.reference.wrx = (self.0.buffer.reference.wrx & !RDX_MASK) | (rdx << RDX_SHIFT);
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.wrx = (reference.wrx & !RDX_MASK) | (rdx << RDX_SHIFT);
     }
 
     #[inline]
@@ -172,41 +203,41 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        self.0.len = (self.0.len & !INLINE_RDX_MASK) | (rdx << INLINE_RDX_SHIFT);
+        self.0.len.set((self.0.len.get() & !Self::INLINE_RDX_MASK) | (rdx << Self::INLINE_RDX_SHIFT));
     }
 
     #[inline]
     fn cap_reference(&self) -> usize {
-        (self.0.buffer.reference.offset & CAP_MASK) >> CAP_SHIFT
+        (unsafe { self.0.buffer.reference.offset } & CAP_MASK) >> CAP_SHIFT
     }
 
+    #[inline]
     fn cap_inlined(&self) -> usize {
         if !INLINE_SUPPORT {
             unreachable!();
         }
-        todo!()
+        INLINE_WORDS * size_of::<usize>()
     }
 
     #[inline]
     fn set_cap_reference(&mut self, cap: usize) {
-        self.0.buffer.reference.offset = (self.0.buffer.reference.offset & !CAP_MASK) | (cap << CAP_SHIFT);
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.offset = (reference.offset & !CAP_MASK) | (cap << CAP_SHIFT);
     }
 
-    fn set_cap_inlined(&mut self, cap: usize) {
-        if !INLINE_SUPPORT {
-            unreachable!();
-        }
-        todo!()
+    fn set_cap_inlined(&mut self, _cap: usize) {
+        // the inline storage has a fixed size
+        unreachable!();
     }
 
     #[inline]
     fn ptr_reference(&self) -> *mut u8 {
-        self.0.buffer.reference.ptr
+        unsafe { self.0.buffer.reference.ptr }
     }
 
     #[inline]
     fn ptr_inlined(&self) -> *mut u8 {
-        (&self.0.buffer.inlined as *const [usize; 3]).cast::<u8>().cast_mut()
+        ptr::addr_of!(self.0.buffer.inlined).cast::<u8>().cast_mut()
     }
 
     #[inline]
@@ -216,18 +247,28 @@ impl<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE
 
     #[inline]
     fn flags(&self) -> Self::FlagsTy {
-        BufferTy(self.0.len & BUFFER_TY_MASK)
+        BufferTy(self.0.len.get() & FLAGS_MASK)
+    }
+
+    #[inline]
+    fn mark_shared(&self) {
+        self.0.len.clear_shared(UNIQUE_BUFFER_FLAG);
     }
 }
 
-const INLINE_BUFFER_FLAG: usize = 1 << (usize::BITS - 1);
+pub(crate) const INLINE_BUFFER_FLAG: usize = 1 << (usize::BITS - 1);
 const STATIC_BUFFER_FLAG: usize = 1 << (usize::BITS - 2);
+/// external buffers use the last remaining combination of the flag bits
+const EXTERNAL_BUFFER_FLAG: usize = INLINE_BUFFER_FLAG | STATIC_BUFFER_FLAG;
+/// unique buffers use one of the unused bits right below the buffer type bits
+pub(crate) const UNIQUE_BUFFER_FLAG: usize = 1 << (usize::BITS - 3);
 
 /// the last 2 bits will never be used as allocations are capped at usize::MAX / 8 * 5
 const BUFFER_TY_MASK: usize = build_bit_mask(usize::BITS as usize - 2, 2);
+pub(crate) const FLAGS_MASK: usize = BUFFER_TY_MASK | UNIQUE_BUFFER_FLAG;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-struct BufferTy<const SUPPORT_INLINE: bool, const SUPPORT_STATIC: bool = true>(usize);
+pub struct BufferTy<const SUPPORT_INLINE: bool, const SUPPORT_STATIC: bool = true>(pub(crate) usize);
 
 impl<const SUPPORT_INLINE: bool, const SUPPORT_STATIC: bool> Flags for BufferTy<SUPPORT_INLINE, SUPPORT_STATIC> {
     #[inline]
@@ -251,6 +292,14 @@ impl<const SUPPORT_INLINE: bool, const SUPPORT_STATIC: bool> Flags for BufferTy<
         Self(0)
     }
 
+    #[inline]
+    fn new_external() -> Self {
+        if !SUPPORT_STATIC {
+            unreachable!();
+        }
+        Self(EXTERNAL_BUFFER_FLAG)
+    }
+
     #[inline]
     fn is_inlined(self) -> bool {
         SUPPORT_INLINE && self.0 == INLINE_BUFFER_FLAG
@@ -263,6 +312,21 @@ impl<const SUPPORT_INLINE: bool, const SUPPORT_STATIC: bool> Flags for BufferTy<
 
     #[inline]
     fn is_reference(self) -> bool {
-        (!SUPPORT_INLINE && !SUPPORT_STATIC) || self.0 == 0
+        (!SUPPORT_INLINE && !SUPPORT_STATIC) || self.0 & BUFFER_TY_MASK == 0
+    }
+
+    #[inline]
+    fn is_external(self) -> bool {
+        SUPPORT_STATIC && self.0 == EXTERNAL_BUFFER_FLAG
+    }
+
+    #[inline]
+    fn new_unique() -> Self {
+        Self(UNIQUE_BUFFER_FLAG)
+    }
+
+    #[inline]
+    fn is_unique(self) -> bool {
+        self.0 & UNIQUE_BUFFER_FLAG != 0
     }
 }
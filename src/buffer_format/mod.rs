@@ -1,15 +1,34 @@
 use std::fmt::Debug;
+use std::ptr::slice_from_raw_parts;
+
+use crate::external::ExternalHeader;
+use crate::ref_cnt::RefCnt;
+use crate::stats;
+use crate::storage::HeapStorage;
+use crate::util::{aligned_cap, claim_unique, dealloc, meta_align, meta_of, realloc_buffer_counted, realloc_buffer_counted_aligned};
 
 pub mod half;
 pub mod extended;
+pub mod packed;
 
 pub trait BufferFormat<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool = true>: Sized + Clone {
 
     type FlagsTy: Flags;
 
+    /// the way the reference counter of outlined buffers gets updated.
+    type RefCntTy: RefCnt;
+
+    /// the number of bytes inlined buffers can store.
+    const INLINE_SIZE: usize;
+
+    /// the largest length, capacity, offset and index outlined buffers can store.
+    const MAX_REFERENCE_SIZE: usize;
+
     fn new_reference(len: usize, cap: usize, wrx: usize, rdx: usize, offset: usize, ptr: *mut u8, flags: Self::FlagsTy) -> Self;
 
-    fn new_inlined(len: usize, offset: usize, value: [usize; 3]) -> Self;
+    /// creates an inlined buffer whose storage starts with `value`, which may
+    /// not be longer than `INLINE_SIZE`.
+    fn new_inlined(len: usize, offset: usize, value: &[u8]) -> Self;
 
     fn len_reference(&self) -> usize;
 
@@ -57,9 +76,9 @@ pub trait BufferFormat<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool =
     #[inline]
     fn set_offset(&mut self, offset: usize) {
         if self.flags().is_inlined() {
-            self.offset_inlined()
+            self.set_offset_inlined(offset);
         } else {
-            self.offset_reference()
+            self.set_offset_reference(offset);
         }
     }
 
@@ -145,10 +164,14 @@ pub trait BufferFormat<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool =
 
     fn ptr_inlined(&self) -> *mut u8;
 
+    /// returns a pointer to the start of the buffer's data.
     #[inline]
     fn ptr(&self) -> *mut u8 {
         if self.flags().is_inlined() {
             self.ptr_inlined()
+        } else if self.flags().is_external() {
+            // the reference ptr of external buffers points to their header
+            unsafe { ExternalHeader::data(self.ptr_reference()) }
         } else {
             self.ptr_reference()
         }
@@ -158,6 +181,11 @@ pub trait BufferFormat<const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool =
 
     fn flags(&self) -> Self::FlagsTy;
 
+    /// Clears the unique flag of the layout and publishes the metadata that was set up
+    /// before. This is the only update that may happen through a shared reference, as
+    /// cloning a unique buffer has to share its allocation.
+    fn mark_shared(&self);
+
 }
 
 pub trait Flags: Sized + Copy + Clone + Debug + PartialEq {
@@ -168,6 +196,9 @@ pub trait Flags: Sized + Copy + Clone + Debug + PartialEq {
 
     fn new_reference() -> Self;
 
+    /// External buffers are only supported by formats that support static buffers.
+    fn new_external() -> Self;
+
     fn is_inlined(self) -> bool;
 
     fn is_static_reference(self) -> bool;
@@ -175,4 +206,96 @@ pub trait Flags: Sized + Copy + Clone + Debug + PartialEq {
     /// Whether the buffer layout is a non-static reference.
     fn is_reference(self) -> bool;
 
+    /// Whether the buffer layout references storage that is owned by an external header.
+    fn is_external(self) -> bool;
+
+    /// Unique buffers are heap references that are known to be the only reference to
+    /// their allocation, their metadata isn't initialized until they get shared.
+    fn new_unique() -> Self;
+
+    /// Whether the buffer layout is a unique reference, this implies `is_reference`.
+    fn is_unique(self) -> bool;
+
+}
+
+/// Moves `layout` into the format `TO`. Inlined data stays inlined if it fits into the
+/// inline storage of `TO` and is copied into a new allocation with `additional_cap` bytes
+/// of room for the metadata otherwise. The same happens to outlined data whose storage
+/// can't be addressed by `TO`.
+///
+/// Static data can only be moved into formats that support static storage.
+pub(crate) fn convert_layout<const INLINE: bool, const STATIC_FROM: bool, const STATIC_TO: bool, FROM: BufferFormat<INLINE, STATIC_FROM>, TO: BufferFormat<INLINE, STATIC_TO>>(layout: FROM, additional_cap: usize) -> TO {
+    let flags = layout.flags();
+    if !flags.is_inlined() {
+        let (len, cap, offset) = (layout.len_reference(), layout.cap_reference(), layout.offset_reference());
+        // `TO` can't update a counter that other buffers update differently, so such storage
+        // may only be moved over once nobody else references it. External storage keeps
+        // updating its counter through its vtable, so it always stays with `FROM`.
+        let foreign_cnt = FROM::RefCntTy::ATOMIC != TO::RefCntTy::ATOMIC && !flags.is_static_reference() && !flags.is_unique()
+            && (flags.is_external() || !unsafe { claim_unique::<FROM::RefCntTy>(meta_of(layout.ptr_reference(), cap)) });
+        if len.max(cap).max(offset) <= TO::MAX_REFERENCE_SIZE && !foreign_cnt {
+            return TO::new_reference(len, cap, layout.wrx_reference(), layout.rdx_reference(), offset, layout.ptr_reference(), convert_flags(flags));
+        }
+        // the storage is too large for `TO` or still shared through a counter `TO` can't
+        // update, so only the data gets moved over
+        let wrx = layout.wrx_reference();
+        // only shared heap allocations record their alignment
+        let align = if flags.is_unique() || flags.is_static_reference() || flags.is_external() {
+            1
+        } else {
+            unsafe { meta_align(layout.ptr_reference(), cap) }
+        };
+        let cap = aligned_cap(wrx + additional_cap, align);
+        assert!(cap <= TO::MAX_REFERENCE_SIZE, "tried converting a buffer of {} bytes into a format that supports at most {} bytes", wrx, TO::MAX_REFERENCE_SIZE);
+        let alloc = unsafe { realloc_buffer_counted_aligned(layout.ptr(), offset, wrx, cap, align) };
+        unsafe { release_storage::<INLINE, STATIC_FROM, FROM>(&layout); }
+        stats::on_conversion_copy();
+        return TO::new_reference(wrx, cap, wrx, layout.rdx_reference(), 0, alloc, TO::FlagsTy::new_reference());
+    }
+    let offset = layout.offset_inlined();
+    let wrx = layout.wrx_inlined();
+    let rdx = layout.rdx_inlined();
+    if wrx <= TO::INLINE_SIZE {
+        let data = unsafe { &*slice_from_raw_parts(layout.ptr_inlined().add(offset), wrx) };
+        // the whole inline storage can be written to
+        let mut ret = TO::new_inlined(TO::INLINE_SIZE, 0, data);
+        ret.set_wrx_inlined(wrx);
+        ret.set_rdx_inlined(rdx);
+        return ret;
+    }
+    let cap = wrx + additional_cap;
+    let alloc = unsafe { realloc_buffer_counted(layout.ptr_inlined(), offset, wrx, cap) };
+    stats::on_outline();
+    stats::on_conversion_copy();
+    TO::new_reference(wrx, cap, wrx, rdx, 0, alloc, TO::FlagsTy::new_reference())
+}
+
+/// releases the reference the outlined `layout` holds to its storage.
+///
+/// SAFETY: the layout may not be used afterwards.
+unsafe fn release_storage<const INLINE: bool, const STATIC: bool, L: BufferFormat<INLINE, STATIC>>(layout: &L) {
+    let flags = layout.flags();
+    let (ptr, cap) = (layout.ptr_reference(), layout.cap_reference());
+    if flags.is_static_reference() {
+        // static data is never freed
+    } else if flags.is_unique() {
+        unsafe { dealloc(ptr, cap); }
+    } else if flags.is_external() {
+        unsafe { (ExternalHeader::vtable(ptr).drop)(ptr, cap); }
+    } else {
+        unsafe { (HeapStorage::<L::RefCntTy>::VTABLE.drop)(ptr, cap); }
+    }
+}
+
+#[inline]
+fn convert_flags<FROM: Flags, TO: Flags>(flags: FROM) -> TO {
+    if flags.is_unique() {
+        TO::new_unique()
+    } else if flags.is_static_reference() {
+        TO::new_static_reference()
+    } else if flags.is_external() {
+        TO::new_external()
+    } else {
+        TO::new_reference()
+    }
 }
@@ -0,0 +1,247 @@
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::ptr;
+
+use crate::{buffer_layout::{LenWord, PackedBuffer, INLINE_WORDS_DEFAULT, BufferUnion, ReferenceBuffer}, util::build_bit_mask};
+
+use crate::ref_cnt::{AtomicRefCnt, RefCnt};
+
+use super::{BufferFormat, half::{BufferTy, FLAGS_MASK, INLINE_BUFFER_FLAG, UNIQUE_BUFFER_FLAG, LEN_MASK, LEN_SHIFT, RDX_MASK, RDX_SHIFT, WRX_MASK, WRX_SHIFT, CAP_MASK, CAP_SHIFT, OFFSET_MASK, OFFSET_SHIFT, MAX_REFERENCE_SIZE}};
+
+/// the number of bytes at the top of the len word which hold the flags and the inline indices.
+const INLINE_META_BYTES: usize = 3;
+const INLINE_META_SHIFT: usize = usize::BITS as usize - INLINE_META_BYTES * 8;
+
+/// on little endian systems the low bytes of the len word directly follow the union,
+/// so the inline storage can extend into them.
+const INLINE_SIZE: usize = if cfg!(target_endian = "little") {
+    (INLINE_WORDS_DEFAULT + 1) * size_of::<usize>() - INLINE_META_BYTES
+} else {
+    INLINE_WORDS_DEFAULT * size_of::<usize>()
+};
+const INLINE_SIZE_BITS: usize = (usize::BITS - INLINE_SIZE.leading_zeros()) as usize;
+
+const INLINE_LEN_MASK: usize = build_bit_mask(INLINE_LEN_SHIFT, INLINE_SIZE_BITS);
+const INLINE_LEN_SHIFT: usize = INLINE_META_SHIFT;
+const INLINE_OFFSET_MASK: usize = build_bit_mask(INLINE_OFFSET_SHIFT, INLINE_SIZE_BITS);
+const INLINE_OFFSET_SHIFT: usize = INLINE_META_SHIFT + INLINE_SIZE_BITS;
+const INLINE_WRX_MASK: usize = build_bit_mask(INLINE_WRX_SHIFT, INLINE_SIZE_BITS);
+const INLINE_WRX_SHIFT: usize = INLINE_META_SHIFT + INLINE_SIZE_BITS * 2;
+const INLINE_RDX_MASK: usize = build_bit_mask(INLINE_RDX_SHIFT, INLINE_SIZE_BITS);
+const INLINE_RDX_SHIFT: usize = INLINE_META_SHIFT + INLINE_SIZE_BITS * 3;
+
+// the 4 inline indices have to fit below the flags
+const _: () = assert!(INLINE_SIZE_BITS * 4 <= INLINE_META_BYTES * 8 - 3);
+
+// representation on 64 bit little endian systems:
+//
+// referenced buffers are stored like in `FormatHalf`, only the len word comes last.
+//
+// inlined buffers:
+// 1.-3. word: data[24 bytes]
+// 4. word: data[5 bytes], len[5 bits], offset[5 bits], wrx[5 bits], rdx[5 bits], unused[1 bit], flags[3 bits]
+//
+// -> 29 bytes of inline storage
+//
+// on big endian systems the low bytes of the len word come last, so only
+// the first 3 words are available for inline storage.
+
+/// This format stores more data inline than `FormatHalf` at the same size, it's
+/// limited to the same maximum capacity of `size_of(usize) / 2`.
+#[derive(Clone)]
+pub struct FormatPacked<RC: RefCnt = AtomicRefCnt>(PackedBuffer, PhantomData<RC>);
+
+impl<RC: RefCnt, const INLINE_SUPPORT: bool, const STATIC_SUPPORT: bool> BufferFormat<INLINE_SUPPORT, STATIC_SUPPORT> for FormatPacked<RC> {
+    type FlagsTy = BufferTy<INLINE_SUPPORT, STATIC_SUPPORT>;
+    type RefCntTy = RC;
+
+    const INLINE_SIZE: usize = INLINE_SIZE;
+    const MAX_REFERENCE_SIZE: usize = MAX_REFERENCE_SIZE;
+
+    #[inline]
+    fn new_reference(len: usize, cap: usize, wrx: usize, rdx: usize, offset: usize, ptr: *mut u8, flags: Self::FlagsTy) -> Self {
+        // the capacity is stored exactly like in `FormatHalf`, so it doesn't need to be translated
+        debug_assert!(len.max(cap).max(wrx).max(offset) <= MAX_REFERENCE_SIZE);
+        Self(PackedBuffer {
+            buffer: BufferUnion {
+                reference: ReferenceBuffer {
+                    wrx: (wrx << WRX_SHIFT) | (rdx << RDX_SHIFT),
+                    offset: (offset << OFFSET_SHIFT) | (cap << CAP_SHIFT),
+                    ptr,
+                },
+            },
+            len: LenWord::new((len << LEN_SHIFT) | flags.0),
+        }, PhantomData)
+    }
+
+    #[inline]
+    fn new_inlined(len: usize, offset: usize, value: &[u8]) -> Self {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        debug_assert!(value.len() <= INLINE_SIZE);
+        let mut ret = Self(PackedBuffer {
+            buffer: BufferUnion { inlined: [0; INLINE_WORDS_DEFAULT] },
+            len: LenWord::new((len << INLINE_LEN_SHIFT) | (offset << INLINE_OFFSET_SHIFT) | INLINE_BUFFER_FLAG),
+        }, PhantomData);
+        // the data may extend into the low bytes of the len word which the metadata doesn't use
+        unsafe { ptr::copy_nonoverlapping(value.as_ptr(), ptr::addr_of_mut!(ret.0).cast::<u8>(), value.len()); }
+        ret
+    }
+
+    #[inline]
+    fn len_reference(&self) -> usize {
+        (self.0.len.get() & LEN_MASK) >> LEN_SHIFT
+    }
+
+    #[inline]
+    fn len_inlined(&self) -> usize {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        (self.0.len.get() & INLINE_LEN_MASK) >> INLINE_LEN_SHIFT
+    }
+
+    #[inline]
+    fn set_len_reference(&mut self, len: usize) {
+        self.0.len.set((self.0.len.get() & !LEN_MASK) | (len << LEN_SHIFT));
+    }
+
+    #[inline]
+    fn set_len_inlined(&mut self, len: usize) {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        self.0.len.set((self.0.len.get() & !INLINE_LEN_MASK) | (len << INLINE_LEN_SHIFT));
+    }
+
+    #[inline]
+    fn offset_reference(&self) -> usize {
+        (unsafe { self.0.buffer.reference.offset } & OFFSET_MASK) >> OFFSET_SHIFT
+    }
+
+    #[inline]
+    fn offset_inlined(&self) -> usize {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        (self.0.len.get() & INLINE_OFFSET_MASK) >> INLINE_OFFSET_SHIFT
+    }
+
+    #[inline]
+    fn set_offset_reference(&mut self, offset: usize) {
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.offset = (reference.offset & !OFFSET_MASK) | (offset << OFFSET_SHIFT);
+    }
+
+    #[inline]
+    fn set_offset_inlined(&mut self, offset: usize) {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        self.0.len.set((self.0.len.get() & !INLINE_OFFSET_MASK) | (offset << INLINE_OFFSET_SHIFT));
+    }
+
+    #[inline]
+    fn wrx_reference(&self) -> usize {
+        (unsafe { self.0.buffer.reference.wrx } & WRX_MASK) >> WRX_SHIFT
+    }
+
+    #[inline]
+    fn wrx_inlined(&self) -> usize {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        (self.0.len.get() & INLINE_WRX_MASK) >> INLINE_WRX_SHIFT
+    }
+
+    #[inline]
+    fn set_wrx_reference(&mut self, wrx: usize) {
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.wrx = (reference.wrx & !WRX_MASK) | (wrx << WRX_SHIFT);
+    }
+
+    #[inline]
+    fn set_wrx_inlined(&mut self, wrx: usize) {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        self.0.len.set((self.0.len.get() & !INLINE_WRX_MASK) | (wrx << INLINE_WRX_SHIFT));
+    }
+
+    #[inline]
+    fn rdx_reference(&self) -> usize {
+        (unsafe { self.0.buffer.reference.wrx } & RDX_MASK) >> RDX_SHIFT
+    }
+
+    #[inline]
+    fn rdx_inlined(&self) -> usize {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        (self.0.len.get() & INLINE_RDX_MASK) >> INLINE_RDX_SHIFT
+    }
+
+    #[inline]
+    fn set_rdx_reference(&mut self, rdx: usize) {
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.wrx = (reference.wrx & !RDX_MASK) | (rdx << RDX_SHIFT);
+    }
+
+    #[inline]
+    fn set_rdx_inlined(&mut self, rdx: usize) {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        self.0.len.set((self.0.len.get() & !INLINE_RDX_MASK) | (rdx << INLINE_RDX_SHIFT));
+    }
+
+    #[inline]
+    fn cap_reference(&self) -> usize {
+        (unsafe { self.0.buffer.reference.offset } & CAP_MASK) >> CAP_SHIFT
+    }
+
+    #[inline]
+    fn cap_inlined(&self) -> usize {
+        if !INLINE_SUPPORT {
+            unreachable!();
+        }
+        INLINE_SIZE
+    }
+
+    #[inline]
+    fn set_cap_reference(&mut self, cap: usize) {
+        let reference = unsafe { &mut self.0.buffer.reference };
+        reference.offset = (reference.offset & !CAP_MASK) | (cap << CAP_SHIFT);
+    }
+
+    fn set_cap_inlined(&mut self, _cap: usize) {
+        // the inline storage has a fixed size
+        unreachable!();
+    }
+
+    #[inline]
+    fn ptr_reference(&self) -> *mut u8 {
+        unsafe { self.0.buffer.reference.ptr }
+    }
+
+    #[inline]
+    fn ptr_inlined(&self) -> *mut u8 {
+        (&self.0 as *const PackedBuffer).cast::<u8>().cast_mut()
+    }
+
+    #[inline]
+    fn set_ptr_reference(&mut self, ptr: *mut u8) {
+        self.0.buffer.reference.ptr = ptr;
+    }
+
+    #[inline]
+    fn flags(&self) -> Self::FlagsTy {
+        BufferTy(self.0.len.get() & FLAGS_MASK)
+    }
+
+    #[inline]
+    fn mark_shared(&self) {
+        self.0.len.clear_shared(UNIQUE_BUFFER_FLAG);
+    }
+}
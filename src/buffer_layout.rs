@@ -1,20 +1,70 @@
 use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use crate::util::round_up_pow_2;
+/// the number of words inlined buffers store their data in unless configured otherwise.
+pub(crate) const INLINE_WORDS_DEFAULT: usize = 3;
 
-/// This is the size of the `inlined` field in `BufferUnion`
-pub(crate) const INLINE_SIZE: usize = size_of::<[usize; 3]>();
-pub(crate) const INLINE_SIZE_BITS: usize = round_up_pow_2(INLINE_SIZE).trailing_zeros() as usize;
+/// returns the number of bits required to store any index into the `inlined`
+/// field of a `BufferUnion` with `inline_words` words.
+pub(crate) const fn inline_size_bits(inline_words: usize) -> usize {
+    let size = inline_words * size_of::<usize>();
+    (usize::BITS - size.leading_zeros()) as usize
+}
+
+#[derive(Clone)]
+pub(crate) struct BaseBuffer<const INLINE_WORDS: usize = INLINE_WORDS_DEFAULT> {
+    pub(crate) len: LenWord,
+    pub(crate) buffer: BufferUnion<INLINE_WORDS>,
+}
 
+/// Like `BaseBuffer` but with the `len` word placed behind the storage, so the
+/// bytes of it which aren't used by the flags can extend the inline storage.
 #[derive(Clone)]
-pub(crate) struct BaseBuffer {
-    pub(crate) len: usize,
+#[repr(C)]
+pub(crate) struct PackedBuffer {
     pub(crate) buffer: BufferUnion,
+    pub(crate) len: LenWord,
+}
+
+/// The word holding the flags of a buffer. Unique buffers that get cloned are marked as
+/// shared through a shared reference, so the word is always accessed atomically.
+#[repr(transparent)]
+pub(crate) struct LenWord(AtomicUsize);
+
+impl LenWord {
+    #[inline]
+    pub(crate) const fn new(val: usize) -> Self {
+        Self(AtomicUsize::new(val))
+    }
+
+    #[inline]
+    pub(crate) fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn set(&mut self, val: usize) {
+        *self.0.get_mut() = val;
+    }
+
+    /// clears `bits` and publishes all writes that happened before to threads
+    /// which observe the cleared bits.
+    #[inline]
+    pub(crate) fn clear_shared(&self, bits: usize) {
+        self.0.fetch_and(!bits, Ordering::Release);
+    }
+}
+
+impl Clone for LenWord {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self::new(self.get())
+    }
 }
 
 #[derive(Copy, Clone)]
-pub(crate) union BufferUnion {
-    pub(crate) inlined: [usize; 3],
+pub(crate) union BufferUnion<const INLINE_WORDS: usize = INLINE_WORDS_DEFAULT> {
+    pub(crate) inlined: [usize; INLINE_WORDS],
     pub(crate) reference: ReferenceBuffer,
 }
 
@@ -23,4 +73,4 @@ pub(crate) struct ReferenceBuffer {
     pub(crate) wrx: usize,
     pub(crate) offset: usize,
     pub(crate) ptr: *mut u8,
-}
\ No newline at end of file
+}
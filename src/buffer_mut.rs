@@ -1,47 +1,63 @@
-use std::borrow::Borrow;
-use std::mem::{align_of, size_of};
+use std::borrow::{Borrow, Cow};
+use std::mem::{align_of, ManuallyDrop};
 use std::ops::Deref;
 use std::{mem, ptr};
 use std::ptr::slice_from_raw_parts;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use crate::buffer_format::{BufferFormat, Flags};
+use std::sync::Arc;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use crate::buffer_format::{convert_layout, BufferFormat, Flags};
+use crate::ref_cnt::{LocalRefCnt, RefCnt};
 use crate::buffer_format::half::FormatHalf;
-use crate::{buffer, buffer_rw, GenericBuffer, WritableBuffer};
-use crate::util::{align_unaligned_ptr_to, alloc_uninit_buffer, alloc_zeroed_buffer, dealloc, empty_sentinel, find_sufficient_cap, min, realloc_buffer, realloc_buffer_and_dealloc, realloc_buffer_counted, round_up_pow_2};
-
-pub type BufferMut = BufferMutGeneric;
-
-// TODO: once const_generic_expressions are supported calculate INITIAL_CAP the following:
-// INITIAL_CAP = GROWTH_FACTOR * INLINE_SIZE
-const INITIAL_CAP_DEFAULT: usize = (2 * INLINE_SIZE).next_power_of_two();
-
-pub struct BufferMutGeneric<LAYOUT: BufferFormat<INLINE_SMALL, false> = FormatHalf, const GROWTH_FACTOR: usize = 2, const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT, const INLINE_SMALL: bool = true, const RETAIN_INDICES: bool = true>(pub(crate) LAYOUT);
+use crate::stats;
+use crate::config::DefaultConfig;
+use crate::growth::{sufficient_cap, Doubling, GrowthPolicy};
+use std::marker::PhantomData;
+use crate::{GenericBuffer, GenericWritableBuffer, WritableBuffer};
+use crate::storage::{HeapStorage, StaticStorage, StorageVTable};
+use crate::buffer::{BufferGeneric, INITIAL_CAP_DEFAULT};
+use crate::buffer_rw::BufferRWGeneric;
+use crate::util::{align_unaligned_ptr_to, aligned_cap, alloc_uninit_buffer, alloc_uninit_buffer_aligned, alloc_zeroed_buffer, dealloc, empty_sentinel, fits_meta, init_meta, meta_align, realloc_buffer, with_share_lock, Metadata, METADATA_SIZE};
+
+crate::buffer_types!(@mut DefaultConfig, BufferMut);
+/// A buffer which uses a non-atomic reference counter, it can't be sent or shared across threads.
+pub type LocalBufferMut = BufferMutGeneric<FormatHalf<LocalRefCnt>>;
+
+/// A mutable buffer, whose allocation is only reference counted once it gets shared
+/// with other buffers.
+pub struct BufferMutGeneric<LAYOUT: BufferFormat<INLINE_SMALL, false> = FormatHalf, GROWTH: GrowthPolicy = Doubling, const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT, const INLINE_SMALL: bool = true, const RETAIN_INDICES: bool = true>(pub(crate) LAYOUT, pub(crate) PhantomData<GROWTH>);
 
 // FIXME: only allow cap to be a multiple of meta_align in order to be able to use the lower bits to store the additional size that was masked off to align the metadata properly
 
 // TODO: additional features: allow aligning the ref cnt ptr to the cache line size
 
-/// this is only
-pub(crate) const BASE_INLINE_SIZE: usize = size_of::<BufferMutGeneric<0, 0, false>>() - size_of::<usize>();
-const INLINE_SIZE: usize = min(min(BASE_INLINE_SIZE, buffer::BASE_INLINE_SIZE), buffer_rw::BASE_INLINE_SIZE);
-/// this additional storage is used to store the reference counter and
+/// this additional storage is used to store the metadata and
 /// to align said values properly.
 const ADDITIONAL_BUFFER_CAP: usize = METADATA_SIZE + align_of::<usize>() - 1;
-const METADATA_SIZE: usize = size_of::<usize>() * 1;
 
-unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-Send for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {}
-unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-Sync for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {}
+macro_rules! put_ints_front {
+    ($($name:ident: $ty:ty, $conv:ident;)*) => {
+        $(
+            #[inline]
+            pub fn $name(&mut self, val: $ty) {
+                self.prepend_slice(&val.$conv());
+            }
+        )*
+    };
+}
+
+unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Send for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> where LAYOUT::RefCntTy: Send {}
+unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Sync for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> where LAYOUT::RefCntTy: Sync {}
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-GenericBuffer for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+GenericBuffer for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
     #[inline]
     fn new() -> Self {
         if INLINE_SMALL {
-            Self(LAYOUT::new_inlined(INLINE_SIZE, 0, [0; 3]))
+            Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &[]), PhantomData)
         } else {
-            Self(LAYOUT::new_reference(0, 0, 0, 0, 0, empty_sentinel(), LAYOUT::FlagsTy::new_reference()))
+            Self(LAYOUT::new_reference(0, 0, 0, 0, 0, empty_sentinel(), LAYOUT::FlagsTy::new_reference()), PhantomData)
         }
     }
 
@@ -54,15 +70,26 @@ GenericBuffer for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SM
     fn clear(&mut self) {
         self.0.set_rdx(0);
         self.0.set_wrx(0);
+        if !RETAIN_INDICES && (self.is_inlined() || unsafe { self.claim_unique() }) {
+            // reclaim the space in front of our view as nobody else can observe it
+            let len = self.0.len() + self.0.offset();
+            self.0.set_len(len);
+            self.0.set_offset(0);
+        }
     }
 
+    /// Buffers whose data fits into the inline storage are moved inline, even if
+    /// their storage is shared.
     fn shrink(&mut self) {
         if self.is_inlined() {
             // we have nothing to do as the buffer is stored in line
             return;
         }
-        let target_cap = self.0.wrx_reference() + ADDITIONAL_BUFFER_CAP;
-        if self.0.len_reference() <= target_cap {
+        if self.try_demote() {
+            return;
+        }
+        let target_cap = aligned_cap(self.0.wrx_reference() + ADDITIONAL_BUFFER_CAP, unsafe { self.alloc_align() });
+        if self.0.cap_reference() <= target_cap {
             // we have nothing to do as our capacity is already as small as possible
             return;
         }
@@ -70,55 +97,46 @@ GenericBuffer for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SM
             // for now we just nop if there are other references to the buffer
             return;
         }
-        let old_buf = self.0.ptr_reference();
-        let alloc = unsafe { realloc_buffer_counted(old_buf, self.0.offset_reference(), self.0.wrx_reference(), target_cap) };
-        
-        if unsafe { self.decrement_ref_cnt() } == 0 {
-            unsafe { dealloc(old_buf, self.0.cap_reference()); }
-        }
-        self.0.set_ptr_reference(alloc);
-        self.0.set_cap_reference(target_cap);
-        self.0.set_len_reference(self.0.wrx_reference());
+        // shrinking drops the headroom as well
+        unsafe { self.move_to_alloc(0, target_cap); }
     }
 
     #[inline]
     fn truncate(&mut self, len: usize) {
         if self.len() > len {
-            self.0.set_rdx(self.0.rdx().min(len));
-            self.0.set_wrx(self.0.wrx().min(len));
+            let rdx = self.0.rdx().min(len);
+            self.0.set_rdx(rdx);
+            let wrx = self.0.wrx().min(len);
+            self.0.set_wrx(wrx);
         }
     }
 
+    /// unlike `Buffer`, small parts aren't moved inline, as each part keeps writing into
+    /// its share of the storage and `unsplit` can only rejoin parts that still share it.
     fn split_off(&mut self, offset: usize) -> Self {
-        if self.is_inlined() {
-            let other = Self(LAYOUT::new_inlined(self.0.len_inlined() - self.0.wrx_inlined() - offset, self.0.offset_inlined() + self.0.wrx_inlined() + offset, unsafe { *self.0.ptr_inlined().cast::<[usize; 3]>() }));
-            self.0.set_len_inlined(self.0.wrx_inlined() + offset);
-            other
-        } else {
-            unsafe { self.increment_ref_cnt(); }
-            let other = Self(LAYOUT::new_reference(self.0.len_reference() - self.0.wrx_reference() - offset, self.0.cap_reference(), 0, 0, self.0.offset_inlined() + self.0.wrx_inlined() + offset, unsafe { *self.0.ptr_inlined().cast::<[usize; 3]>() }, self.0.flags()));
-            self.0.set_len_reference(self.0.wrx_reference() + offset);
-            other
-        }
+        let idx = self.0.wrx() + offset;
+        assert!(self.0.len() >= idx, "tried splitting buffer with capacity {} at {}", self.0.len(), idx);
+        let mut other = self.split_ref();
+        let (start, len) = (other.0.offset() + idx, other.0.len() - idx);
+        other.0.set_offset(start);
+        other.0.set_len(len);
+        other.0.set_wrx(0);
+        other.0.set_rdx(0);
+        self.0.set_len(idx);
+        other
     }
 
     fn split_to(&mut self, offset: usize) -> Self {
-        if self.is_inlined() {
-            let other = Self(LAYOUT::new_inlined(self.0.wrx_inlined() + offset, self.0.offset_inlined(), unsafe { *self.0.ptr_inlined().cast::<[usize; 3]>() }));
-            other.0.set_wrx_inlined(self.0.wrx_inlined());
-            other.0.set_rdx_inlined(self.0.rdx_inlined());
-            self.0.set_len_inlined(self.0.wrx_inlined() + offset);
-            self.0.set_rdx_inlined(0);
-            self.0.set_offset_inlined(self.0.offset_inlined() + self.0.wrx_inlined() + offset);
-            self.0.set_wrx_inlined(0);
-        } else {
-            unsafe { self.increment_ref_cnt(); }
-            let other = Self(LAYOUT::new_reference(self.0.wrx_reference() + offset, self.0.cap_reference(), self.0.wrx_reference(), self.0.rdx_reference(), self.0.offset_reference(), self.0.ptr_reference(), self.0.flags()));
-            self.0.set_len_reference(self.0.wrx_reference() + offset);
-            self.0.set_rdx_reference(0);
-            self.0.set_offset_reference(self.0.offset_reference() + self.0.wrx_reference() + offset);
-            self.0.set_wrx_reference(0);
-        }
+        let idx = self.0.wrx() + offset;
+        assert!(self.0.len() >= idx, "tried splitting buffer with capacity {} at {}", self.0.len(), idx);
+        let mut other = self.split_ref();
+        other.0.set_len(idx);
+        let (start, len) = (self.0.offset() + idx, self.0.len() - idx);
+        self.0.set_offset(start);
+        self.0.set_len(len);
+        self.0.set_wrx(0);
+        self.0.set_rdx(0);
+        other
     }
 
     fn split(&mut self) -> Self {
@@ -126,19 +144,20 @@ GenericBuffer for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SM
     }
 
     fn unsplit(&mut self, other: Self) {
-        self.try_unsplit(other).unwrap();
+        if self.try_unsplit(other).is_err() {
+            panic!("Unsplitting only works on buffers that are next to each other");
+        }
     }
 
     fn try_unsplit(&mut self, other: Self) -> Result<(), Self> {
         if self.0.flags() != other.0.flags() {
             return Err(other);
         }
-        let own_off = self.0.offset();
-        let other_off = other.0.offset();
-        let (min, max) = if own_off < other_off {
-            (&self, &other)
+        let this = &*self;
+        let (min, max) = if this.0.offset() < other.0.offset() {
+            (this, &other)
         } else {
-            (&other, &self)
+            (&other, this)
         };
 
         // check if the left buffer still has uninit data
@@ -153,84 +172,390 @@ GenericBuffer for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SM
         }
 
         // check if ptrs aren't matching
-        if !self.0.flags().is_inlined() && min.0.ptr_reference() != max.0.ptr_reference() {
+        if !self.is_inlined() && min.0.ptr_reference() != max.0.ptr_reference() {
             return Err(other);
         }
 
-        self.0.set_len_inlined(self.0.len() + other.0.len());
-        self.0.set_offset(self.0.offset().min(other.0.offset()));
-        self.0.set_wrx(self.0.wrx() + other.0.wrx());
-        self.0.set_rdx(0);
+        // the combined buffer continues reading where the front part left off
+        let rdx = if RETAIN_INDICES { min.0.rdx() } else { 0 };
+        let len = min.0.len() + max.0.len();
+        let offset = min.0.offset();
+        if self.is_inlined() {
+            // inlined parts have their own storage, so the data of the other part has to be copied over
+            unsafe { ptr::copy_nonoverlapping(other.0.ptr_inlined().add(other.0.offset_inlined()), self.0.ptr_inlined().add(other.0.offset_inlined()), other.0.wrx_inlined()); }
+        }
+        self.0.set_len(len);
+        self.0.set_offset(offset);
+        let wrx = self.0.wrx() + other.0.wrx();
+        self.0.set_wrx(wrx);
+        self.0.set_rdx(rdx);
         Ok(())
     }
 
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+
+    /// Allocates a buffer with at least `capacity` bytes of capacity whose data
+    /// starts at an address that is a multiple of `align`. The alignment is
+    /// retained when the buffer has to grow.
+    ///
+    /// Note that such buffers are never stored inline as that wouldn't
+    /// allow us to guarantee the alignment.
+    ///
+    /// #Panic
+    /// Panics if `align` isn't a power of two.
+    pub fn with_capacity_aligned(capacity: usize, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "alignment {} is not a power of two", align);
+        let cap = aligned_cap(capacity + ADDITIONAL_BUFFER_CAP, align);
+        let alloc = unsafe { alloc_uninit_buffer_aligned(cap, align) };
+        let ret = Self(LAYOUT::new_reference(cap - ADDITIONAL_BUFFER_CAP, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_reference()), PhantomData);
+        // set ref cnt and alignment
+        unsafe { Metadata::init(ret.meta_ptr(), align); }
+        ret
+    }
 
     #[inline]
     pub(crate) fn is_inlined(&self) -> bool {
         self.0.flags().is_inlined()
     }
 
+    /// returns whether the buffer is known to be the only reference to its allocation,
+    /// the metadata of such allocations isn't initialized.
+    #[inline]
+    fn is_unique(&self) -> bool {
+        self.0.flags().is_unique()
+    }
+
+    /// Prepares the buffer's allocation for being shared with other buffers.
+    ///
+    /// Freshly allocated buffers skip reference counting until they get cloned, split or
+    /// converted into another buffer type, which happens automatically.
+    #[inline]
+    pub fn share(&mut self) {
+        if !self.is_unique() {
+            return;
+        }
+        if !self.has_meta_room() {
+            // adopted vecs may lack the spare capacity to hold the metadata
+            let cap = self.0.offset_reference() + self.0.len_reference() + ADDITIONAL_BUFFER_CAP;
+            unsafe { self.move_to_alloc(self.0.offset_reference(), cap); }
+        }
+        unsafe { self.init_shared(); }
+    }
+
+    /// returns whether the metadata fits behind the writable part of the allocation,
+    /// this may only be called if the buffer isn't inlined.
+    #[inline]
+    fn has_meta_room(&self) -> bool {
+        self.0.offset_reference() + self.0.len_reference() + ADDITIONAL_BUFFER_CAP <= self.0.cap_reference()
+    }
+
+    /// sets up the metadata of a unique buffer, so its allocation can be shared.
+    ///
+    /// SAFETY: the buffer has to be unique and may not get shared by another thread concurrently.
+    #[inline]
+    unsafe fn init_shared(&self) {
+        // unique buffers are always allocated without a custom alignment
+        unsafe { init_meta(self.0.ptr_reference(), self.0.cap_reference(), 1); }
+        self.0.mark_shared();
+    }
+
+    /// applies `RETAIN_INDICES` to the indices of a buffer that was converted from another buffer type.
+    #[inline]
+    pub(crate) fn adopt_indices(mut self) -> Self {
+        if !RETAIN_INDICES {
+            self.0.set_rdx(0);
+        }
+        self
+    }
+
+    /// takes the layout out of the buffer without releasing the storage it references.
+    #[inline]
+    pub(crate) fn into_layout(self) -> LAYOUT {
+        let this = ManuallyDrop::new(self);
+        unsafe { ptr::read(&this.0) }
+    }
+
+    /// moves the written data into the inline storage if it fits there and releases
+    /// the storage the buffer referenced, so small buffers don't keep large allocations alive.
+    fn try_demote(&mut self) -> bool {
+        if !INLINE_SMALL || self.is_inlined() {
+            return false;
+        }
+        let wrx = self.0.wrx_reference();
+        // the inline storage can't keep the alignment requested through `with_capacity_aligned`
+        if wrx > LAYOUT::INLINE_SIZE || unsafe { self.alloc_align() } > 1 {
+            return false;
+        }
+        let data = unsafe { &*slice_from_raw_parts(self.0.ptr_reference().add(self.0.offset_reference()), wrx) };
+        let mut inlined = Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, data), PhantomData);
+        inlined.0.set_wrx_inlined(wrx);
+        inlined.0.set_rdx_inlined(self.0.rdx_reference());
+        // dropping the old buffer releases its reference to the storage
+        drop(mem::replace(self, inlined));
+        true
+    }
+
     #[inline]
     pub(crate) unsafe fn is_only(&self) -> bool {
+        if self.is_unique() {
+            return true;
+        }
         let meta_ptr = unsafe { self.meta_ptr() };
-        unsafe { &*meta_ptr.cast::<AtomicUsize>() }.load(Ordering::Acquire) == 1
+        LAYOUT::RefCntTy::load(unsafe { &*meta_ptr.cast::<AtomicUsize>() }) == 1
+    }
+
+    /// returns the operations of the storage this buffer references.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    #[inline]
+    pub(crate) unsafe fn vtable(&self) -> &'static StorageVTable {
+        if !INLINE_SMALL && self.0.ptr_reference() == empty_sentinel() {
+            StaticStorage::VTABLE
+        } else {
+            HeapStorage::<LAYOUT::RefCntTy>::VTABLE
+        }
+    }
+
+    /// returns whether this is the only buffer referencing its allocation, if so weak
+    /// buffers can't upgrade anymore, so the allocation may be written to.
+    ///
+    /// SAFETY: this is only safe to call if the buffer isn't inlined.
+    #[inline]
+    pub(crate) unsafe fn claim_unique(&self) -> bool {
+        if self.is_unique() {
+            return true;
+        }
+        unsafe { (self.vtable().is_unique)(self.0.ptr_reference(), self.0.cap_reference()) }
+    }
+
+    /// Ensures that no other buffer views the data of this buffer by copying it into
+    /// a new allocation if the allocation is shared with clones of this buffer.
+    /// This happens automatically on the first write to a shared buffer.
+    ///
+    /// Note that the parts created through `split*` keep sharing their allocation,
+    /// as each of them only writes to its own part of it.
+    #[inline]
+    pub fn make_mut(&mut self) {
+        if self.is_inlined() || (!INLINE_SMALL && self.0.ptr_reference() == empty_sentinel()) {
+            return;
+        }
+        if !unsafe { self.is_writable() } {
+            self.unshare();
+        }
+    }
+
+    /// returns whether this buffer may write to the part of the allocation it views.
+    ///
+    /// SAFETY: this is only safe to call if the buffer isn't inlined.
+    #[inline]
+    unsafe fn is_writable(&self) -> bool {
+        if self.is_unique() {
+            return true;
+        }
+        unsafe { (self.vtable().is_writable)(self.0.ptr_reference(), self.0.cap_reference()) }
+    }
+
+    /// creates a buffer referencing the same allocation, the caller has to narrow the views
+    /// of both buffers down to disjoint parts of it.
+    #[inline]
+    fn split_ref(&mut self) -> Self {
+        self.share();
+        if !self.is_inlined() {
+            unsafe { (self.vtable().split)(self.0.ptr_reference(), self.0.cap_reference()); }
+        }
+        // inlined buffers get copied, so both parts keep the data at its position in the storage
+        Self(self.0.clone(), PhantomData)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn unshare(&mut self) {
+        unsafe { self.move_to_alloc(self.0.offset_reference(), self.0.cap_reference()); }
+    }
+
+    /// Creates a buffer with `headroom` bytes of space in front of its data and at least
+    /// `capacity` bytes of space for the data itself. The headroom can be filled through
+    /// `prepend_slice` and the `put_*_front` methods without moving the data.
+    pub fn with_headroom(headroom: usize, capacity: usize) -> Self {
+        if INLINE_SMALL && headroom + capacity <= LAYOUT::INLINE_SIZE {
+            return Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE - headroom, headroom, &[]), PhantomData);
+        }
+        let cap = headroom + capacity + ADDITIONAL_BUFFER_CAP;
+        let alloc = unsafe { alloc_uninit_buffer(cap) };
+        // the metadata is only initialized once the buffer gets shared
+        Self(LAYOUT::new_reference(capacity, cap, 0, 0, headroom, alloc, LAYOUT::FlagsTy::new_unique()), PhantomData)
+    }
+
+    /// returns the number of bytes that can be prepended without moving the data.
+    #[inline]
+    pub fn headroom(&self) -> usize {
+        self.0.offset()
+    }
+
+    /// Writes `val` in front of the buffer's data. If the headroom is too small, the data is
+    /// moved into a new allocation whose headroom grows according to `GROWTH`.
+    ///
+    /// The reader index isn't adjusted, so the prepended bytes are only read if nothing
+    /// was read from the buffer yet.
+    #[inline]
+    pub fn prepend_slice(&mut self, val: &[u8]) {
+        let ptr = self.ensure_headroom(val.len());
+        unsafe { ptr::copy_nonoverlapping(val.as_ptr(), ptr, val.len()); }
+    }
+
+    #[inline]
+    pub fn put_u8_front(&mut self, val: u8) {
+        let ptr = self.ensure_headroom(1);
+        unsafe { *ptr = val; }
+    }
+
+    put_ints_front! {
+        put_u16_le_front: u16, to_le_bytes;
+        put_u16_be_front: u16, to_be_bytes;
+        put_u16_ne_front: u16, to_ne_bytes;
+        put_u32_le_front: u32, to_le_bytes;
+        put_u32_be_front: u32, to_be_bytes;
+        put_u32_ne_front: u32, to_ne_bytes;
+        put_u64_le_front: u64, to_le_bytes;
+        put_u64_be_front: u64, to_be_bytes;
+        put_u64_ne_front: u64, to_ne_bytes;
+        put_u128_le_front: u128, to_le_bytes;
+        put_u128_be_front: u128, to_be_bytes;
+        put_u128_ne_front: u128, to_ne_bytes;
+    }
+
+    /// moves the start of the data `req` bytes to the front and returns a pointer to it,
+    /// the space in front of the data may belong to other buffers if the storage is shared.
+    #[inline]
+    fn ensure_headroom(&mut self, req: usize) -> *mut u8 {
+        if self.0.offset() < req {
+            self.grow_headroom(req);
+        } else if !self.is_inlined() && !unsafe { self.claim_unique() } {
+            // the headroom may be viewed by parts that were split off in front of this buffer
+            self.unshare();
+        }
+        let offset = self.0.offset() - req;
+        self.0.set_offset(offset);
+        let len = self.0.len() + req;
+        self.0.set_len(len);
+        let wrx = self.0.wrx() + req;
+        self.0.set_wrx(wrx);
+        unsafe { self.0.ptr().add(offset) }
+    }
+
+    /// moves the data into a new allocation with at least `req` bytes of headroom.
+    #[cold]
+    #[inline(never)]
+    fn grow_headroom(&mut self, req: usize) {
+        let wrx = self.0.wrx();
+        let len = self.0.len().max(wrx);
+        let curr = self.0.offset() + len;
+        // grow the whole storage geometrically, so repeated prepends don't reallocate every time
+        let headroom = GROWTH::grow(curr, len + req) - len;
+        let cap = headroom + len + ADDITIONAL_BUFFER_CAP;
+        let alloc = unsafe { alloc_uninit_buffer(cap) };
+        unsafe { ptr::copy_nonoverlapping(self.0.ptr().add(self.0.offset()), alloc.add(headroom), wrx); }
+        if self.is_inlined() {
+            stats::on_outline();
+        } else {
+            unsafe { self.release_alloc(); }
+            stats::on_realloc();
+        }
+        // the metadata is only initialized once the buffer gets shared
+        self.0 = LAYOUT::new_reference(len, cap, wrx, self.0.rdx(), headroom, alloc, LAYOUT::FlagsTy::new_unique());
+    }
+
+    /// moves the data of the buffer into a new allocation of `cap` bytes, placing it behind
+    /// `headroom` bytes, and releases the old one. the new allocation is unique unless it has
+    /// to retain a custom alignment, as that is stored in the metadata.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    unsafe fn move_to_alloc(&mut self, headroom: usize, cap: usize) {
+        let align = unsafe { self.alloc_align() };
+        let cap = aligned_cap(cap, align);
+        let (alloc, flags) = if align == 1 {
+            (unsafe { alloc_uninit_buffer(cap) }, LAYOUT::FlagsTy::new_unique())
+        } else {
+            let alloc = unsafe { alloc_uninit_buffer_aligned(cap, align) };
+            unsafe { init_meta(alloc, cap, align); }
+            (alloc, LAYOUT::FlagsTy::new_reference())
+        };
+        let wrx = self.0.wrx_reference();
+        unsafe { ptr::copy_nonoverlapping(self.0.ptr_reference().add(self.0.offset_reference()), alloc.add(headroom), wrx); }
+        unsafe { self.release_alloc(); }
+        self.0 = LAYOUT::new_reference(cap - ADDITIONAL_BUFFER_CAP - headroom, cap, wrx, self.0.rdx_reference(), headroom, alloc, flags);
+    }
+
+    /// returns the alignment the buffer's allocation was created with.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    #[inline]
+    unsafe fn alloc_align(&self) -> usize {
+        // the empty sentinel has no metadata to read the alignment from
+        if self.is_unique() || (!INLINE_SMALL && self.0.ptr_reference() == empty_sentinel()) {
+            1
+        } else {
+            unsafe { meta_align(self.0.ptr_reference(), self.0.cap_reference()) }
+        }
+    }
+
+    /// releases this buffer's reference to its allocation.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    #[inline]
+    unsafe fn release_alloc(&self) {
+        if self.is_unique() {
+            // unique buffers are always allocated without a custom alignment
+            unsafe { dealloc(self.0.ptr_reference(), self.0.cap_reference()); }
+        } else {
+            unsafe { (self.vtable().drop)(self.0.ptr_reference(), self.0.cap_reference()); }
+        }
     }
 
     #[inline]
     fn ensure_large_enough(&mut self, req: usize) -> *mut u8 {
-        let self_ptr = self as *mut BufferMutGeneric<LAYOUT, { GROWTH_FACTOR }, { INITIAL_CAP }, { INLINE_SMALL }, { RETAIN_INDICES }>;
+        let self_ptr = self as *mut BufferMutGeneric<LAYOUT, GROWTH, { INITIAL_CAP }, { INLINE_SMALL }, { RETAIN_INDICES }>;
         if self.is_inlined() {
             if self.0.len_inlined() < self.0.wrx_inlined() + req {
                 #[cold]
                 #[inline(never)]
-                fn outline_buffer<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>(buffer: *mut BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES>, req: usize) -> *mut u8 {
+                fn outline_buffer<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>(buffer: *mut BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES>, req: usize) -> *mut u8 {
                     let offset = unsafe { (&*buffer).0.offset_inlined() };
-                    // remove the inline flag, wrx and offset data
-                    let len = unsafe { (&*buffer).0.len_inlined() };
                     let rdx = unsafe { (&*buffer).0.rdx_inlined() };
                     let wrx = unsafe { (&*buffer).0.wrx_inlined() };
-                    let cap = find_sufficient_cap::<GROWTH_FACTOR>(INITIAL_CAP, offset + wrx + req + ADDITIONAL_BUFFER_CAP);
+                    let cap = sufficient_cap::<GROWTH>(INITIAL_CAP, offset + wrx + req + ADDITIONAL_BUFFER_CAP);
 
-                    let alloc = unsafe { realloc_buffer_counted((&*buffer).0.ptr_inlined(), offset, wrx, cap) };
-                    unsafe { (&mut *buffer).0.set_cap_reference(cap) };
-                    unsafe { (&mut *buffer).0.set_len_reference(cap - ADDITIONAL_BUFFER_CAP - offset) };
-                    unsafe { (&mut *buffer).0.set_ptr_reference(alloc) };
-                    unsafe { (&mut *buffer).0.set_offset_reference(offset) };
-                    unsafe { (&mut *buffer).0.set_wrx_reference(wrx) };
-                    unsafe { (&mut *buffer).0.set_rdx_reference(rdx) };
+                    let alloc = unsafe { alloc_uninit_buffer(cap) };
+                    // the headroom in front of the data is kept
+                    unsafe { ptr::copy_nonoverlapping((&*buffer).0.ptr_inlined().add(offset), alloc.add(offset), wrx); }
+                    stats::on_outline();
+                    // the metadata is only initialized once the buffer gets shared
+                    unsafe { (&mut *buffer).0 = LAYOUT::new_reference(cap - ADDITIONAL_BUFFER_CAP - offset, cap, wrx, rdx, offset, alloc, LAYOUT::FlagsTy::new_unique()); }
 
-                    unsafe { alloc.add(wrx) }
+                    unsafe { alloc.add(offset + wrx) }
                 }
                 // handle outlining buffer
                 return outline_buffer(self_ptr, req);
             }
             return unsafe { (&*self_ptr).0.ptr_inlined().add(self.0.offset_inlined() + self.0.wrx_inlined()) };
         }
+        // copy the data before writing to an allocation that's shared with other buffers
+        self.make_mut();
         // handle buffer reallocation
-        if self.0.len_reference() < self.0.wrx_reference() + req + ADDITIONAL_BUFFER_CAP {
+        if self.0.len_reference() < self.0.wrx_reference() + req {
             #[inline(never)]
             #[cold]
-            fn resize_alloc<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICIES: bool>(buffer: *mut BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICIES>, req: usize) {
+            fn resize_alloc<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICIES: bool>(buffer: *mut BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICIES>, req: usize) {
                 let old_cap = unsafe { (&*buffer).0.cap_reference() };
-                let len = unsafe { (&*buffer).0.len_reference() };
-                let new_cap = find_sufficient_cap::<{ GROWTH_FACTOR }>(round_up_pow_2(len).min(old_cap), len + req + ADDITIONAL_BUFFER_CAP);
-                
-                let alloc = unsafe { realloc_buffer((&*buffer).0.ptr_reference(), (&*buffer).0.offset_reference(), len, old_cap, new_cap) };
-
-                if unsafe { (&*buffer).decrement_ref_cnt() } == 0 {
-                    unsafe { dealloc((&*buffer).0.ptr_reference(), (&*buffer).0.cap_reference()); }
-                }
-
-                unsafe { (&mut *buffer).0.set_ptr_reference(alloc); }
-                unsafe { (&mut *buffer).0.set_cap_reference(new_cap); }
-                unsafe { (&mut *buffer).0.set_len_reference(new_cap); }
-                unsafe { (&mut *buffer).0.set_offset_reference(0); }
-                // set ref cnt
-                unsafe { *(&*buffer).meta_ptr().cast::<usize>() = 1; }
+                let offset = unsafe { (&*buffer).0.offset_reference() };
+                let wrx = unsafe { (&*buffer).0.wrx_reference() };
+                let new_cap = sufficient_cap::<GROWTH>(old_cap, offset + wrx + req + ADDITIONAL_BUFFER_CAP);
+                // this keeps the alignment the buffer was originally allocated with and its headroom
+                unsafe { (&mut *buffer).move_to_alloc(offset, new_cap); }
+                stats::on_realloc();
             }
             resize_alloc(self_ptr, req);
         }
@@ -246,50 +571,76 @@ BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICE
 
     #[inline]
     unsafe fn increment_ref_cnt(&self) {
-        unsafe { &*self.meta_ptr().cast::<AtomicUsize>() }.fetch_add(1, Ordering::AcqRel);
+        unsafe { (self.vtable().clone)(self.0.ptr_reference(), self.0.cap_reference()); }
+    }
+
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+WritableBuffer for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+
+    #[inline]
+    fn put_slice(&mut self, val: &[u8]) {
+        let ptr = self.ensure_large_enough(val.len());
+        unsafe { ptr::copy_nonoverlapping(val as *const [u8] as *const u8, ptr, val.len()); }
+        let wrx = self.0.wrx() + val.len();
+        self.0.set_wrx(wrx);
+    }
+
+    #[inline]
+    fn put_bytes(&mut self, val: u8, repeat: usize) {
+        let ptr = self.ensure_large_enough(repeat);
+        unsafe { ptr::write_bytes(ptr, val, repeat); }
+        let wrx = self.0.wrx() + repeat;
+        self.0.set_wrx(wrx);
     }
 
     #[inline]
-    unsafe fn decrement_ref_cnt(&self) -> usize {
-        unsafe { &*self.meta_ptr().cast::<AtomicUsize>() }.fetch_sub(1, Ordering::AcqRel) - 1
+    fn put_u8(&mut self, val: u8) {
+        let ptr = self.ensure_large_enough(1);
+        unsafe { *ptr = val; }
+        let wrx = self.0.wrx() + 1;
+        self.0.set_wrx(wrx);
     }
 
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-WritableBuffer for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+GenericWritableBuffer for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
 
     fn with_capacity(cap: usize) -> Self {
-        if INLINE_SMALL && cap <= INLINE_SIZE {
-            Self(LAYOUT::new_inlined(INLINE_SIZE, 0, [0; 3]))
+        if INLINE_SMALL && cap <= LAYOUT::INLINE_SIZE {
+            Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &[]), PhantomData)
         } else {
+            let len = cap;
             let cap = cap + ADDITIONAL_BUFFER_CAP;
             let alloc = unsafe { alloc_uninit_buffer(cap) };
-            let ret = Self(LAYOUT::new_reference(0, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_reference()));
-            // set ref cnt
-            unsafe { *ret.meta_ptr().cast::<usize>() = 1; }
-            ret
+            // the metadata is only initialized once the buffer gets shared
+            Self(LAYOUT::new_reference(len, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_unique()), PhantomData)
         }
     }
 
     fn zeroed(len: usize) -> Self {
-        if INLINE_SMALL && len <= INLINE_SIZE {
-            Self(LAYOUT::new_inlined(INLINE_SIZE, 0, [0; 3]))
+        if INLINE_SMALL && len <= LAYOUT::INLINE_SIZE {
+            Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &[]), PhantomData)
         } else {
             let cap = len + ADDITIONAL_BUFFER_CAP;
             let alloc = alloc_zeroed_buffer(cap);
-            let ret = Self(LAYOUT::new_reference(len, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_reference()));
-            // set ref cnt
-            unsafe { *ret.meta_ptr().cast::<usize>() = 1; }
-            ret
+            // the metadata is only initialized once the buffer gets shared
+            Self(LAYOUT::new_reference(len, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_unique()), PhantomData)
         }
     }
 
     #[inline]
     fn reset_writer_index(&mut self) {
-        self.0.set_wrx(0);
-        // maintain rdx
-        self.0.set_rdx(0);
+        if RETAIN_INDICES {
+            // keep the data that was already read, so only the unread data gets discarded
+            let wrx = self.0.rdx();
+            self.0.set_wrx(wrx);
+        } else {
+            self.0.set_wrx(0);
+            self.0.set_rdx(0);
+        }
     }
 
     #[inline]
@@ -298,27 +649,6 @@ WritableBuffer for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_S
         self.0.len()
     }
 
-    #[inline]
-    fn put_slice(&mut self, val: &[u8]) {
-        let ptr = self.ensure_large_enough(val.len());
-        unsafe { ptr::copy_nonoverlapping(val as *const [u8] as *const u8, ptr, val.len()); }
-        self.0.set_len(self.0.len() + val.len());
-    }
-
-    #[inline]
-    fn put_bytes(&mut self, val: u8, repeat: usize) {
-        let ptr = self.ensure_large_enough(repeat);
-        unsafe { ptr::write_bytes(ptr, val, repeat); }
-        self.0.set_len(self.0.len() + repeat);
-    }
-
-    #[inline]
-    fn put_u8(&mut self, val: u8) {
-        let ptr = self.ensure_large_enough(1);
-        unsafe { *ptr = val; }
-        self.0.set_len(self.0.len() + 1);
-    }
-
     fn reserve(&mut self, size: usize) {
         self.ensure_large_enough(size);
     }
@@ -327,63 +657,65 @@ WritableBuffer for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_S
         if self.0.len() <= size {
             self.ensure_large_enough(size);
         } else {
-            self.0.set_wrx(self.0.wrx().min(size));
-            self.0.set_rdx(self.0.rdx().min(size));
+            let wrx = self.0.wrx().min(size);
+            self.0.set_wrx(wrx);
+            let rdx = self.0.rdx().min(size);
+            self.0.set_rdx(rdx);
         }
     }
 
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-Drop for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Drop for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
     #[inline]
     fn drop(&mut self) {
         if self.is_inlined() {
             // we don't need to do anything for inlined buffers
             return;
         }
-        if !INLINE_SMALL && self.0.ptr_reference() == empty_sentinel() {
-            // we don't do anything for empty buffers
-            return;
-        }
-        // fast path for single ref cnt scenarios
-        if unsafe { self.is_only() } {
-            unsafe { dealloc(self.0.ptr_reference(), self.0.cap_reference()); }
-            return;
-        }
-        let meta_ptr = unsafe { self.meta_ptr() };
-        let ref_cnt = unsafe { &*meta_ptr.cast::<AtomicUsize>() };
-        let remaining = ref_cnt.fetch_sub(1, Ordering::AcqRel) - 1; // FIXME: can we choose a weaker ordering?
-        if remaining == 0 {
-            unsafe { dealloc(self.0.ptr_reference(), self.0.cap_reference()); }
-        }
+        unsafe { self.release_alloc(); }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-Clone for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Clone for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
     #[inline]
     fn clone(&self) -> Self {
-        if self.is_inlined() {
-            return Self(self.0.clone());
+        if self.is_unique() {
+            if !self.has_meta_room() {
+                // the data can't be moved behind a shared reference, so the clone gets its own allocation
+                let cap = self.0.offset_reference() + self.0.len_reference() + ADDITIONAL_BUFFER_CAP;
+                let alloc = unsafe { alloc_uninit_buffer(cap) };
+                unsafe { ptr::copy_nonoverlapping(self.0.ptr_reference().add(self.0.offset_reference()), alloc.add(self.0.offset_reference()), self.0.wrx_reference()); }
+                return Self(LAYOUT::new_reference(self.0.len_reference(), cap, self.0.wrx_reference(), self.0.rdx_reference(), self.0.offset_reference(), alloc, LAYOUT::FlagsTy::new_unique()), PhantomData);
+            }
+            // the metadata is only initialized once the allocation gets shared, other threads
+            // may clone the buffer at the same time, so only one of them sets it up
+            with_share_lock(|| if self.is_unique() {
+                unsafe { self.init_shared(); }
+            });
         }
-
-        let alloc = unsafe { realloc_buffer_counted(self.0.ptr_reference(), self.0.offset_reference(), self.0.wrx_reference(), self.0.cap_reference()) };
-        
-        Self(LAYOUT::new_reference(self.0.len_reference(), self.0.cap_reference(), self.0.wrx_reference(), self.0.rdx_reference(), self.0.offset_reference(), alloc, self.0.flags()))
+        if !self.is_inlined() {
+            // synchronize with the thread which set up the metadata
+            fence(Ordering::Acquire);
+            // the allocation is shared until one of the buffers gets written to
+            unsafe { self.increment_ref_cnt(); }
+        }
+        Self(self.0.clone(), PhantomData)
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-AsRef<[u8]> for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+AsRef<[u8]> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
     #[inline]
     fn as_ref(&self) -> &[u8] {
-        unsafe { &*slice_from_raw_parts(self.0.ptr(), self.len()) }
+        unsafe { &*slice_from_raw_parts(self.0.ptr().add(self.0.offset()), self.len()) }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-Deref for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Deref for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
     type Target = [u8];
 
     #[inline]
@@ -392,64 +724,150 @@ Deref for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RET
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-Borrow<[u8]> for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Borrow<[u8]> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
     #[inline]
     fn borrow(&self) -> &[u8] {
         self.as_ref()
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-Default for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Default for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
     #[inline]
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-Into<Vec<u8>> for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+From<BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES>> for Vec<u8> {
     #[inline]
-    fn into(self) -> Vec<u8> {
+    fn from(buffer: BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES>) -> Self {
          // FIXME: should we add ADDITIONAL_BUFFER_CAP on realloc?
 
         // handle inlined buffers
-        if self.is_inlined() {
-            let alloc = unsafe { realloc_buffer(self.0.ptr_inlined(), self.0.offset_inlined(), self.0.len_inlined(), self.0.len_inlined()) };
-            return unsafe { Vec::from_raw_parts(alloc, self.0.len(), self.0.len()) };
-        }
-        let len = self.0.len_reference();
-        // try reusing buffer
-        if unsafe { self.is_only() } && self.0.offset_reference() == 0 {
-            let ret = unsafe { Vec::from_raw_parts(self.0.ptr_reference(), len, self.0.cap_reference()) };
-            mem::forget(self);
+        if buffer.is_inlined() {
+            let alloc = unsafe { realloc_buffer(buffer.0.ptr_inlined(), buffer.0.offset_inlined(), buffer.len(), buffer.len()) };
+            stats::on_hand_over(buffer.len());
+            stats::on_conversion_copy();
+            return unsafe { Vec::from_raw_parts(alloc, buffer.len(), buffer.len()) };
+        }
+        let this = ManuallyDrop::new(buffer);
+        if this.is_unique() {
+            // unique allocations don't need any checks before being reused
+            if this.0.offset_reference() == 0 {
+                stats::on_hand_over(this.0.cap_reference());
+                return unsafe { Vec::from_raw_parts(this.0.ptr_reference(), this.0.wrx_reference(), this.0.cap_reference()) };
+            }
+            let ret = this.as_ref().to_vec();
+            stats::on_conversion_copy();
+            unsafe { this.release_alloc(); }
             return ret;
         }
-        // FIXME: should we try to shrink?
-        let buf = unsafe { realloc_buffer(self.0.ptr_reference(), self.0.offset_reference(), len, self.0.cap_reference()) };
-        unsafe { Vec::from_raw_parts(buf, len, self.0.cap_reference()) }
+        // the storage decides whether it can be reused, this consumes our reference
+        let vtable = unsafe { this.vtable() };
+        unsafe { (vtable.to_vec)(this.0.ptr_reference(), this.0.cap_reference(), this.0.offset_reference(), this.0.wrx_reference()) }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
-From<Vec<u8>> for BufferMutGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+From<Vec<u8>> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    /// Adopts the vec's allocation as it is, the metadata is only set up once the buffer gets shared.
+    /// If the vec's spare capacity can't hold the metadata, the data is moved at that point or
+    /// once the buffer grows, whichever happens first.
     fn from(mut value: Vec<u8>) -> Self {
         let ptr = value.as_mut_ptr();
         let cap = value.capacity();
-        let mut len = value.len();
+        let len = value.len();
         // handle small buffers
-        if INLINE_SMALL && len <= INLINE_SIZE {
+        if INLINE_SMALL && len <= LAYOUT::INLINE_SIZE {
             // FIXME: should we instead keep the small buffer if it exists already and doesn't cost us anything?
-            let mut ret = Self(LAYOUT::new_inlined(len, 0, [0; 3]));
-            unsafe { ptr::copy_nonoverlapping(ptr, ret.0.ptr_inlined(), len); }
+            let mut ret = Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &value), PhantomData);
+            ret.0.set_wrx_inlined(len);
             return ret;
         }
+        if cap == 0 {
+            // the vec didn't allocate, so there is nothing to adopt
+            return Self::new();
+        }
         mem::forget(value);
-        // reuse existing buffer
-        let ret = Self(LAYOUT::new_reference(len, cap, 0, 0, 0, ptr, LAYOUT::FlagsTy::new_reference()));
-        // set ref cnt
-        unsafe { *ret.meta_ptr().cast::<usize>() = 1; }
-        ret
+        stats::on_adopt(cap);
+        // the spare capacity in front of the metadata can be written to
+        let writable = if fits_meta(ptr, len, cap) {
+            cap - ADDITIONAL_BUFFER_CAP
+        } else {
+            len
+        };
+        Self(LAYOUT::new_reference(writable, cap, len, 0, 0, ptr, LAYOUT::FlagsTy::new_unique()), PhantomData)
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+From<Box<[u8]>> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: Box<[u8]>) -> Self {
+        Self::from(Vec::from(value))
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+From<String> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: String) -> Self {
+        Self::from(value.into_bytes())
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+From<&'static str> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: &'static str) -> Self {
+        // mutable buffers can't reference static data, so we have to copy it
+        Self::from(value.as_bytes().to_vec())
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+From<Cow<'static, [u8]>> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: Cow<'static, [u8]>) -> Self {
+        match value {
+            Cow::Borrowed(value) => Self::from(value.to_vec()),
+            Cow::Owned(value) => Self::from(value),
+        }
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+From<Arc<[u8]>> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: Arc<[u8]>) -> Self {
+        // the data may be shared with others, so we have to copy it
+        Self::from(value.to_vec())
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, OtherLayout: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, const COMPACT_DIVISOR_OTHER: usize, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<BufferGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER, COMPACT_DIVISOR_OTHER>> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    /// Reuses the allocation of the buffer and carries over its indices according to
+    /// `RETAIN_INDICES`, the allocation is copied on the first write if it's still shared
+    /// by then. Static and external data is copied right away, just like inlined data
+    /// that doesn't fit into the inline storage of this buffer.
+    #[inline]
+    fn from(value: BufferGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER, COMPACT_DIVISOR_OTHER>) -> Self {
+        Self(convert_layout::<INLINE_SMALL, STATIC_STORAGE, false, _, _>(value.into_owned_layout(), ADDITIONAL_BUFFER_CAP), PhantomData).adopt_indices()
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, OtherLayout: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<BufferRWGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER>> for BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    /// Reuses the allocation of the read-write buffer and carries over its indices according
+    /// to `RETAIN_INDICES`, the allocation is copied on the first write if it's still shared
+    /// by then. Static data is copied right away, just like inlined data that doesn't fit
+    /// into the inline storage of this buffer.
+    #[inline]
+    fn from(value: BufferRWGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER>) -> Self {
+        Self(convert_layout::<INLINE_SMALL, STATIC_STORAGE, false, _, _>(value.into_owned_layout(), ADDITIONAL_BUFFER_CAP), PhantomData).adopt_indices()
     }
 }
@@ -0,0 +1,148 @@
+use std::borrow::Borrow;
+use std::ops::Deref;
+use crate::buffer::BufferGeneric;
+use crate::buffer::INITIAL_CAP_DEFAULT;
+use crate::buffer_format::BufferFormat;
+use crate::buffer_format::half::FormatHalf;
+use crate::buffer_mut::BufferMutGeneric;
+use crate::config::DefaultConfig;
+use crate::growth::{Doubling, GrowthPolicy};
+use crate::GenericBuffer;
+
+crate::buffer_types!(@reverse DefaultConfig, ReverseBufferMut);
+
+/// A mutable buffer which is written back to front, every `put_*` call writes in front of
+/// the bytes that were written before. This suits formats whose offsets point towards the
+/// end of the data, like FlatBuffers.
+///
+/// The written bytes are located at the end of the buffer's storage, growing moves them
+/// to the end of the new storage. Once done, the buffer can be frozen into a `Buffer`
+/// without copying the data.
+pub struct ReverseBufferMutGeneric<LAYOUT: BufferFormat<INLINE_SMALL, false> = FormatHalf, GROWTH: GrowthPolicy = Doubling, const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT, const INLINE_SMALL: bool = true, const RETAIN_INDICES: bool = true>(BufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES>);
+
+macro_rules! put_ints {
+    ($($name:ident: $ty:ty, $front:ident;)*) => {
+        $(
+            #[inline]
+            pub fn $name(&mut self, val: $ty) {
+                self.0.$front(val);
+            }
+        )*
+    };
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+ReverseBufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+
+    #[inline]
+    pub fn new() -> Self {
+        if !INLINE_SMALL {
+            return Self(BufferMutGeneric::new());
+        }
+        Self::with_capacity(0)
+    }
+
+    /// Creates a buffer which can hold at least `capacity` bytes before it has to grow.
+    #[inline]
+    pub fn with_capacity(capacity: usize) -> Self {
+        // the whole inline storage is free to be written
+        let capacity = if INLINE_SMALL && capacity <= LAYOUT::INLINE_SIZE {
+            LAYOUT::INLINE_SIZE
+        } else {
+            capacity
+        };
+        Self(BufferMutGeneric::with_headroom(capacity, 0))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// returns the number of bytes that can be written before the buffer has to grow.
+    #[inline]
+    pub fn headroom(&self) -> usize {
+        self.0.headroom()
+    }
+
+    /// Writes `val` in front of the bytes written before.
+    #[inline]
+    pub fn put_slice(&mut self, val: &[u8]) {
+        self.0.prepend_slice(val);
+    }
+
+    #[inline]
+    pub fn put_u8(&mut self, val: u8) {
+        self.0.put_u8_front(val);
+    }
+
+    put_ints! {
+        put_u16_le: u16, put_u16_le_front;
+        put_u16_be: u16, put_u16_be_front;
+        put_u16_ne: u16, put_u16_ne_front;
+        put_u32_le: u32, put_u32_le_front;
+        put_u32_be: u32, put_u32_be_front;
+        put_u32_ne: u32, put_u32_ne_front;
+        put_u64_le: u64, put_u64_le_front;
+        put_u64_be: u64, put_u64_be_front;
+        put_u64_ne: u64, put_u64_ne_front;
+        put_u128_le: u128, put_u128_le_front;
+        put_u128_be: u128, put_u128_be_front;
+        put_u128_ne: u128, put_u128_ne_front;
+    }
+
+    /// Converts the written bytes into a read-only buffer, this only copies them if they're
+    /// inlined and don't fit into the inline storage of the read-only buffer.
+    #[inline]
+    pub fn freeze<OtherLayout: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const STATIC_STORAGE: bool, const RETAIN_INDICES_OTHER: bool, const COMPACT_DIVISOR: usize>(self) -> BufferGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER, COMPACT_DIVISOR> {
+        BufferGeneric::from(self.0)
+    }
+
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Clone for ReverseBufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Default for ReverseBufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+AsRef<[u8]> for ReverseBufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Deref for ReverseBufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.as_ref()
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, false>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const RETAIN_INDICES: bool>
+Borrow<[u8]> for ReverseBufferMutGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, RETAIN_INDICES> {
+    #[inline]
+    fn borrow(&self) -> &[u8] {
+        self.as_ref()
+    }
+}
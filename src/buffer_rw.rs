@@ -1,41 +1,42 @@
-use std::borrow::Borrow;
-use std::mem::{align_of, size_of};
+use std::borrow::{Borrow, Cow};
+use std::mem::{align_of, ManuallyDrop};
 use std::ops::Deref;
 use std::{mem, ptr};
-use std::cmp::Ord;
-use std::ptr::{null_mut, slice_from_raw_parts};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use crate::buffer_format::{BufferFormat, Flags};
+use std::ptr::slice_from_raw_parts;
+use std::sync::Arc;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use crate::buffer_format::{convert_layout, BufferFormat, Flags};
+use crate::ref_cnt::{LocalRefCnt, RefCnt};
 use crate::buffer_format::half::FormatHalf;
-use crate::util::{align_unaligned_ptr_to, alloc_uninit_buffer, alloc_zeroed_buffer, build_bit_mask, dealloc, empty_sentinel, find_sufficient_cap, min, realloc_buffer, realloc_buffer_and_dealloc, realloc_buffer_counted};
-use crate::{buffer, buffer_mut, GenericBuffer, ReadableBuffer, RWBuffer, WritableBuffer};
-use crate::buffer::BufferGeneric;
+use crate::stats;
+use crate::config::DefaultConfig;
+use crate::growth::{sufficient_cap, Doubling, GrowthPolicy};
+use std::marker::PhantomData;
+use crate::util::{align_unaligned_ptr_to, aligned_cap, alloc_uninit_buffer, alloc_zeroed_buffer, dealloc, empty_sentinel, fits_meta, init_meta, meta_align, realloc_buffer, realloc_buffer_counted, realloc_buffer_counted_aligned, with_share_lock, METADATA_SIZE};
+use crate::{GenericBuffer, GenericReadableBuffer, GenericWritableBuffer, ReadableBuffer, RWBuffer, WritableBuffer};
+use crate::storage::{HeapStorage, StaticStorage, StorageVTable};
+use crate::buffer::{BufferGeneric, INITIAL_CAP_DEFAULT};
 use crate::buffer_mut::BufferMutGeneric;
 
-pub type BufferRW = BufferRWGeneric;
-
-// TODO: once const_generic_expressions are supported calculate INITIAL_CAP the following:
-// INITIAL_CAP = GROWTH_FACTOR * INLINE_SIZE
-const INITIAL_CAP_DEFAULT: usize = (2 * INLINE_SIZE).next_power_of_two();
+crate::buffer_types!(@rw DefaultConfig, BufferRW);
+/// A buffer which uses a non-atomic reference counter, it can't be sent or shared across threads.
+pub type LocalBufferRW = BufferRWGeneric<FormatHalf<LocalRefCnt>>;
 
 #[repr(C)]
-pub struct BufferRWGeneric<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE> = FormatHalf, const GROWTH_FACTOR: usize = 2, const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT, const INLINE_SMALL: bool = true, const STATIC_STORAGE: bool = true, const RETAIN_INDICES: bool = true>(pub(crate) LAYOUT);
+pub struct BufferRWGeneric<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE> = FormatHalf, GROWTH: GrowthPolicy = Doubling, const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT, const INLINE_SMALL: bool = true, const STATIC_STORAGE: bool = true, const RETAIN_INDICES: bool = true>(pub(crate) LAYOUT, pub(crate) PhantomData<GROWTH>);
 
 // FIXME: move both flags into `len` as we only need 3/4 of the available space in len.
-pub(crate) const BASE_INLINE_SIZE: usize = size_of::<BufferRWGeneric<FormatHalf, 0, 0, false, false>>() - size_of::<usize>();
-const INLINE_SIZE: usize = min(min(BASE_INLINE_SIZE, buffer_mut::BASE_INLINE_SIZE), buffer::BASE_INLINE_SIZE);
-/// this additional storage is used to store the reference counter and
+/// this additional storage is used to store the metadata and
 /// to align said values properly.
 const ADDITIONAL_BUFFER_CAP: usize = METADATA_SIZE + align_of::<usize>() - 1;
-const METADATA_SIZE: usize = size_of::<usize>() * 1;
 
-unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Send for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {}
-unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Sync for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {}
+unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+Send for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> where LAYOUT::RefCntTy: Send {}
+unsafe impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+Sync for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> where LAYOUT::RefCntTy: Sync {}
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
 
     #[inline]
     pub(crate) fn is_static(&self) -> bool {
@@ -44,68 +45,283 @@ BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE
 
     #[inline]
     pub(crate) fn is_inlined(&self) -> bool {
-        INLINE_SMALL && self.0.flags().is_reference()
+        INLINE_SMALL && self.0.flags().is_inlined()
+    }
+
+    /// returns whether the buffer is known to be the only reference to its allocation,
+    /// the metadata of such allocations isn't initialized.
+    #[inline]
+    fn is_unique(&self) -> bool {
+        self.0.flags().is_unique()
+    }
+
+    /// Prepares the buffer's allocation for being shared with other buffers.
+    ///
+    /// Allocations made by this buffer skip reference counting until they get cloned,
+    /// split or converted into an immutable buffer, which happens automatically.
+    #[inline]
+    pub fn share(&mut self) {
+        if !self.is_unique() {
+            return;
+        }
+        if !self.has_meta_room() {
+            // adopted vecs may lack the spare capacity to hold the metadata
+            let cap = self.0.len_reference() + ADDITIONAL_BUFFER_CAP;
+            unsafe { self.move_to_alloc(cap); }
+        }
+        unsafe { self.init_shared(); }
+    }
+
+    /// returns whether the metadata fits behind the writable part of the allocation,
+    /// this may only be called if the buffer isn't inlined.
+    #[inline]
+    fn has_meta_room(&self) -> bool {
+        self.0.offset_reference() + self.0.len_reference() + ADDITIONAL_BUFFER_CAP <= self.0.cap_reference()
+    }
+
+    /// sets up the metadata of a unique buffer, so its allocation can be shared.
+    ///
+    /// SAFETY: the buffer has to be unique and may not get shared by another thread concurrently.
+    #[inline]
+    unsafe fn init_shared(&self) {
+        // unique buffers are always allocated without a custom alignment
+        unsafe { init_meta(self.0.ptr_reference(), self.0.cap_reference(), 1); }
+        self.0.mark_shared();
+    }
+
+    /// releases this buffer's reference to its storage.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    #[inline]
+    unsafe fn release_alloc(&self) {
+        if self.is_unique() {
+            // unique buffers are always allocated without a custom alignment
+            unsafe { dealloc(self.0.ptr_reference(), self.0.cap_reference()); }
+        } else {
+            unsafe { (self.vtable().drop)(self.0.ptr_reference(), self.0.cap_reference()); }
+        }
+    }
+
+    /// moves the written data into the inline storage if it fits there and releases
+    /// the storage the buffer referenced, so small buffers don't keep large allocations alive.
+    fn try_demote(&mut self) -> bool {
+        if !INLINE_SMALL || self.is_inlined() || self.is_static() {
+            // static data is never freed, so there is nothing to release
+            return false;
+        }
+        let wrx = self.0.wrx_reference();
+        // the inline storage can't keep the alignment of the allocation
+        if wrx > LAYOUT::INLINE_SIZE || unsafe { self.alloc_align() } > 1 {
+            return false;
+        }
+        let data = unsafe { &*slice_from_raw_parts(self.0.ptr_reference().add(self.0.offset_reference()), wrx) };
+        let mut inlined = Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, data), PhantomData);
+        inlined.0.set_wrx_inlined(wrx);
+        inlined.0.set_rdx_inlined(self.0.rdx_reference());
+        // dropping the old buffer releases its reference to the storage
+        drop(mem::replace(self, inlined));
+        true
     }
 
     /// SAFETY: this is only safe to call if the buffer isn't inlined and isn't static.
     #[inline]
     pub(crate) unsafe fn is_only(&self) -> bool {
+        if self.is_unique() {
+            return true;
+        }
         let meta_ptr = unsafe { self.meta_ptr() };
-        unsafe { &*meta_ptr.cast::<AtomicUsize>() }.load(Ordering::Acquire) == 1
+        LAYOUT::RefCntTy::load(unsafe { &*meta_ptr.cast::<AtomicUsize>() }) == 1
+    }
+
+    /// returns the operations of the storage this buffer references.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    #[inline]
+    pub(crate) unsafe fn vtable(&self) -> &'static StorageVTable {
+        if self.is_static() || self.is_empty_sentinel() {
+            StaticStorage::VTABLE
+        } else {
+            HeapStorage::<LAYOUT::RefCntTy>::VTABLE
+        }
+    }
+
+    /// returns whether the buffer references the empty sentinel, which is used
+    /// if the buffer supports neither inlined nor static storage.
+    #[inline]
+    fn is_empty_sentinel(&self) -> bool {
+        !INLINE_SMALL && !STATIC_STORAGE && self.0.ptr_reference() == empty_sentinel()
+    }
+
+    /// returns whether this is the only buffer referencing its storage and the storage
+    /// may be mutated, if so weak buffers can't upgrade anymore.
+    ///
+    /// SAFETY: this is only safe to call if the buffer isn't inlined.
+    #[inline]
+    pub(crate) unsafe fn claim_unique(&self) -> bool {
+        if self.is_unique() {
+            return true;
+        }
+        unsafe { (self.vtable().is_unique)(self.0.ptr_reference(), self.0.cap_reference()) }
+    }
+
+    /// Converts this buffer into a mutable buffer without copying its data, this
+    /// only succeeds if the buffer is inlined or if it's the only buffer referencing
+    /// its allocation. Otherwise the buffer is returned unchanged.
+    ///
+    /// Inlined data is copied into a new allocation if it doesn't fit into the inline
+    /// storage of the mutable buffer.
+    pub fn try_into_mut<OtherLayout: BufferFormat<INLINE_SMALL, false>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool>(self) -> Result<BufferMutGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>, Self> {
+        // static buffers are never unique as they can't be written to
+        if !self.is_inlined() && !unsafe { self.claim_unique() } {
+            return Err(self);
+        }
+        Ok(BufferMutGeneric(convert_layout::<INLINE_SMALL, STATIC_STORAGE, false, _, _>(self.into_layout(), ADDITIONAL_BUFFER_CAP), PhantomData).adopt_indices())
+    }
+
+    /// applies `RETAIN_INDICES` to the indices of a buffer that was converted from another buffer type.
+    #[inline]
+    fn adopt_indices(mut self) -> Self {
+        if !RETAIN_INDICES {
+            self.0.set_rdx(0);
+        }
+        self
+    }
+
+    /// takes the layout out of the buffer without releasing the storage it references.
+    #[inline]
+    pub(crate) fn into_layout(self) -> LAYOUT {
+        let this = ManuallyDrop::new(self);
+        unsafe { ptr::read(&this.0) }
+    }
+
+    /// takes the layout out of the buffer, static data is copied into a new allocation
+    /// so that the returned layout is either inlined or references heap storage.
+    pub(crate) fn into_owned_layout(self) -> LAYOUT {
+        if self.is_static() {
+            let len = self.0.wrx_reference();
+            let cap = len + ADDITIONAL_BUFFER_CAP;
+            let alloc = unsafe { realloc_buffer_counted(self.0.ptr_reference(), self.0.offset_reference(), len, cap) };
+            stats::on_static_promotion();
+            stats::on_conversion_copy();
+            return LAYOUT::new_reference(len, cap, len, self.0.rdx_reference(), 0, alloc, LAYOUT::FlagsTy::new_reference());
+        }
+        self.into_layout()
+    }
+
+    /// Ensures that no other buffer views the data of this buffer by copying it into
+    /// a new allocation if the storage is shared with clones of this buffer.
+    /// This happens automatically on the first write to a shared buffer.
+    ///
+    /// Note that static buffers are copied on the first write as well, while the parts
+    /// created through `split*` keep sharing their allocation.
+    #[inline]
+    pub fn make_mut(&mut self) {
+        if self.is_inlined() || self.is_static() || self.is_empty_sentinel() || self.is_unique() {
+            return;
+        }
+        if !unsafe { (self.vtable().is_writable)(self.0.ptr_reference(), self.0.cap_reference()) } {
+            self.unshare();
+        }
+    }
+
+    /// creates a buffer referencing the same storage, the caller has to narrow the views
+    /// of both buffers down to disjoint parts of it.
+    #[inline]
+    fn split_ref(&mut self) -> Self {
+        self.share();
+        if !self.is_inlined() {
+            unsafe { (self.vtable().split)(self.0.ptr_reference(), self.0.cap_reference()); }
+        }
+        // inlined buffers get copied, so both parts keep the data at its position in the storage
+        Self(self.0.clone(), PhantomData)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn unshare(&mut self) {
+        unsafe { self.move_to_alloc(self.0.cap_reference()); }
+    }
+
+    /// returns the alignment the buffer's storage was allocated with.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    #[inline]
+    unsafe fn alloc_align(&self) -> usize {
+        if self.is_unique() || self.is_static() || self.is_empty_sentinel() {
+            1
+        } else {
+            unsafe { meta_align(self.0.ptr_reference(), self.0.cap_reference()) }
+        }
+    }
+
+    /// moves the written data of the buffer into a new allocation of `cap` bytes and
+    /// releases the old storage. the alignment of heap allocations is retained, the new
+    /// allocation is unique unless it has to retain a custom alignment, as that is stored
+    /// in the metadata.
+    ///
+    /// SAFETY: this may only be called if the buffer isn't inlined.
+    unsafe fn move_to_alloc(&mut self, cap: usize) {
+        let align = unsafe { self.alloc_align() };
+        let cap = aligned_cap(cap, align);
+        let wrx = self.0.wrx_reference();
+        let (alloc, flags) = if align == 1 {
+            (unsafe { realloc_buffer(self.0.ptr_reference(), self.0.offset_reference(), wrx, cap) }, LAYOUT::FlagsTy::new_unique())
+        } else {
+            (unsafe { realloc_buffer_counted_aligned(self.0.ptr_reference(), self.0.offset_reference(), wrx, cap, align) }, LAYOUT::FlagsTy::new_reference())
+        };
+        unsafe { self.release_alloc(); }
+        // the data is located at the start of the new allocation
+        self.0 = LAYOUT::new_reference(cap - ADDITIONAL_BUFFER_CAP, cap, wrx, self.0.rdx_reference(), 0, alloc, flags);
     }
 
     #[inline]
     fn ensure_large_enough(&mut self, req: usize) -> *mut u8 {
-        let self_ptr = self as *mut BufferRWGeneric<LAYOUT, { GROWTH_FACTOR }, { INITIAL_CAP }, { INLINE_SMALL }, { STATIC_STORAGE }, { RETAIN_INDICES }>;
+        let self_ptr = self as *mut BufferRWGeneric<LAYOUT, GROWTH, { INITIAL_CAP }, { INLINE_SMALL }, { STATIC_STORAGE }, { RETAIN_INDICES }>;
         if self.is_inlined() {
-            if self.len() + req > INLINE_SIZE {
+            if self.0.len_inlined() < self.0.wrx_inlined() + req {
                 #[cold]
                 #[inline(never)]
-                fn outline_buffer<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>(buffer: *mut BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES>, req: usize) -> *mut u8 {
-                    let rdx = (unsafe { (&*buffer).len } & RDX_MASK) >> LEN_MASK.count_ones();
-                    // remove the inline flag and rdx data
-                    unsafe { (&mut *buffer).len &= !(INLINE_BUFFER_FLAG | RDX_MASK); }
-                    let len = unsafe { (&*buffer).len };
-                    let cap = find_sufficient_cap::<{ GROWTH_FACTOR }>(INITIAL_CAP, len + req + ADDITIONAL_BUFFER_CAP);
-                    let alloc = unsafe { realloc_buffer_counted(buffer.cast::<u8>().add(size_of::<usize>()), len, cap) };
-
-                    unsafe { (&mut *buffer).cap = cap };
-                    unsafe { (&mut *buffer).ptr = alloc };
-                    unsafe { (&mut *buffer).rdx = rdx };
-                    unsafe { alloc.add(len) }
+                fn outline_buffer<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>(buffer: *mut BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES>, req: usize) -> *mut u8 {
+                    let offset = unsafe { (&*buffer).0.offset_inlined() };
+                    let rdx = unsafe { (&*buffer).0.rdx_inlined() };
+                    let wrx = unsafe { (&*buffer).0.wrx_inlined() };
+                    let cap = sufficient_cap::<GROWTH>(INITIAL_CAP, wrx + req + ADDITIONAL_BUFFER_CAP);
+                    let alloc = unsafe { realloc_buffer((&*buffer).0.ptr_inlined(), offset, wrx, cap) };
+                    stats::on_outline();
+
+                    // the metadata is only initialized once the buffer gets shared
+                    unsafe { (&mut *buffer).0 = LAYOUT::new_reference(cap - ADDITIONAL_BUFFER_CAP, cap, wrx, rdx, 0, alloc, LAYOUT::FlagsTy::new_unique()); }
+                    unsafe { alloc.add(wrx) }
                 }
                 // handle outlining buffer
                 return outline_buffer(self_ptr, req);
             }
-            return unsafe { self_ptr.cast::<u8>().add(usize::BITS as usize / 8 + self.len()) };
+            return unsafe { self.0.ptr_inlined().add(self.0.offset_inlined() + self.0.wrx_inlined()) };
         }
         // move the static buffer into a dynamic heap buffer
         if self.is_static() {
-            let cap = find_sufficient_cap::<{ GROWTH_FACTOR }>(INITIAL_CAP, self.len + req + ADDITIONAL_BUFFER_CAP);
-            let alloc = unsafe { realloc_buffer_counted(self.ptr, self.len, cap) };
-            self.ptr = alloc;
-            self.cap = cap;
-            // mark the buffer as non static
-            self.rdx &= !STATIC_BUFFER_FLAG;
-            return unsafe { self.ptr.add(self.len) };
+            let cap = sufficient_cap::<GROWTH>(INITIAL_CAP, self.0.wrx_reference() + req + ADDITIONAL_BUFFER_CAP);
+            unsafe { self.move_to_alloc(cap); }
+            stats::on_static_promotion();
+            return unsafe { self.0.ptr_reference().add(self.0.wrx_reference()) };
         }
+        // copy the data before writing to an allocation that's shared with other buffers
+        self.make_mut();
         // handle buffer reallocation
-        if self.cap < self.len + req + ADDITIONAL_BUFFER_CAP {
+        if self.0.len_reference() < self.0.wrx_reference() + req {
             #[inline(never)]
             #[cold]
-            fn resize_alloc<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>(buffer: *mut BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES>, req: usize) {
-                let old_cap = unsafe { (&*buffer).cap };
-                let len = unsafe { (&*buffer).len };
-                let new_cap = find_sufficient_cap::<{ GROWTH_FACTOR }>(old_cap, len + req + ADDITIONAL_BUFFER_CAP);
-                let new_alloc = unsafe { realloc_buffer_and_dealloc((&*buffer).ptr, len, old_cap, new_cap) };
-                unsafe { (&mut *buffer).ptr = new_alloc; }
-                unsafe { (&mut *buffer).cap = new_cap; }
-                // set ref cnt
-                unsafe { *(&*buffer).meta_ptr().cast::<usize>() = 1; }
+            fn resize_alloc<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>(buffer: *mut BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES>, req: usize) {
+                let old_cap = unsafe { (&*buffer).0.cap_reference() };
+                let wrx = unsafe { (&*buffer).0.wrx_reference() };
+                let new_cap = sufficient_cap::<GROWTH>(old_cap, wrx + req + ADDITIONAL_BUFFER_CAP);
+                // this keeps the alignment the buffer was originally allocated with
+                unsafe { (&mut *buffer).move_to_alloc(new_cap); }
+                stats::on_realloc();
             }
             resize_alloc(self_ptr, req);
         }
-        unsafe { self.ptr.add(self.len) }
+        unsafe { self.0.ptr_reference().add(self.0.offset_reference() + self.0.wrx_reference()) }
     }
 
     #[inline]
@@ -114,16 +330,16 @@ BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE
         if remaining < bytes {
             panic!("not enough bytes in buffer, expected {} readable bytes but only {} bytes are left", bytes, remaining);
         }
-        let rdx = if STATIC_STORAGE {
-            self.rdx & !STATIC_BUFFER_FLAG
-        } else {
-            self.rdx
-        };
-        if self.is_inlined() {
-            unsafe { self.inlined_buffer_ptr().add(rdx) }
-        } else {
-            unsafe { self.ptr.add(rdx) }
-        }
+        unsafe { self.0.ptr().add(self.0.offset() + self.0.rdx()) }
+    }
+
+    /// moves the start of the buffer's view `amount` bytes forward and resets the reader index.
+    #[inline]
+    fn advance_view(&mut self, amount: usize) {
+        self.0.set_offset(self.0.offset() + amount);
+        self.0.set_len(self.0.len() - amount);
+        self.0.set_wrx(self.0.wrx() - amount);
+        self.0.set_rdx(0);
     }
 
     /// SAFETY: this may only be called if the buffer isn't
@@ -133,53 +349,15 @@ BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE
         unsafe { align_unaligned_ptr_to::<{ align_of::<usize>() }, METADATA_SIZE>(self.0.ptr_reference(), self.0.cap_reference()) }
     }
 
-    /// SAFETY: this may only be called if the buffer is inlined.
-    #[inline]
-    unsafe fn inlined_buffer_ptr(&self) -> *mut u8 {
-        let ptr = self as *const BufferRWGeneric<LAYOUT, { GROWTH_FACTOR }, { INITIAL_CAP }, { INLINE_SMALL }, { STATIC_STORAGE }, { RETAIN_INDICES }, { RETAIN_INDICES }>;
-        unsafe { ptr.cast::<u8>().add(size_of::<usize>()) }.cast_mut()
-    }
-
-    #[inline]
-    fn get_rdx(&self) -> usize {
-        if self.is_inlined() {
-            return (self.len & RDX_MASK) >> LEN_MASK.count_ones();
-        }
-        if STATIC_STORAGE {
-            self.rdx & !STATIC_BUFFER_FLAG
-        } else {
-            self.rdx
-        }
-    }
-
     #[inline]
-    fn set_rdx(&mut self, rdx: usize) {
-        if self.is_inlined() {
-            self.len = (self.len & !RDX_MASK) | (rdx << LEN_MASK.count_ones());
-            return;
-        }
-        let flags = if STATIC_STORAGE {
-            self.rdx & STATIC_BUFFER_FLAG
-        } else {
-            0
-        };
-        self.rdx = rdx | flags;
-    }
-
-    #[inline]
-    fn set_len(&mut self, len: usize) {
-        let flags = if INLINE_SMALL {
-            self.len & (INLINE_BUFFER_FLAG | RDX_MASK)
-        } else {
-            0
-        };
-        self.len = len | flags;
+    unsafe fn increment_ref_cnt(&self) {
+        unsafe { (self.vtable().clone)(self.0.ptr_reference(), self.0.cap_reference()); }
     }
 
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-GenericBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+GenericBuffer for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
     fn new() -> Self {
         if !INLINE_SMALL && STATIC_STORAGE {
@@ -188,9 +366,9 @@ GenericBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMA
         }
 
         if INLINE_SMALL {
-            Self(LAYOUT::new_inlined(INLINE_SIZE, 0, [0; 3]))
+            Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &[]), PhantomData)
         } else {
-            Self(LAYOUT::new_reference(0, 0, 0, 0, 0, empty_sentinel(), LAYOUT::FlagsTy::new_reference()))
+            Self(LAYOUT::new_reference(0, 0, 0, 0, 0, empty_sentinel(), LAYOUT::FlagsTy::new_reference()), PhantomData)
         }
     }
 
@@ -203,8 +381,15 @@ GenericBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMA
     fn clear(&mut self) {
         self.0.set_rdx(0);
         self.0.set_wrx(0);
+        if !RETAIN_INDICES && (self.is_inlined() || unsafe { self.claim_unique() }) {
+            // reclaim the space in front of our view as nobody else can observe it
+            self.0.set_len(self.0.len() + self.0.offset());
+            self.0.set_offset(0);
+        }
     }
 
+    /// Buffers whose data fits into the inline storage are moved inline, even if
+    /// their storage is shared.
     fn shrink(&mut self) {
         if self.is_inlined() {
             // we have nothing to do as the buffer is stored in line
@@ -214,24 +399,19 @@ GenericBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMA
             // we have nothing to do as the buffer is static
             return;
         }
-        let target_cap = self.0.wrx_reference() + ADDITIONAL_BUFFER_CAP;
-        if self.0.len_reference() <= target_cap {
+        if self.try_demote() {
+            return;
+        }
+        let target_cap = aligned_cap(self.0.wrx_reference() + ADDITIONAL_BUFFER_CAP, unsafe { self.alloc_align() });
+        if self.0.cap_reference() <= target_cap {
             // we have nothing to do as our capacity is already as small as possible
             return;
         }
-        if !unsafe { self.is_only() } {
+        if self.is_empty_sentinel() || !unsafe { self.is_only() } {
             // for now we just nop if there are other references to the buffer
             return;
         }
-        let old_buf = self.0.ptr_reference();
-        let alloc = unsafe { realloc_buffer_counted(old_buf, self.0.offset_reference(), self.0.wrx_reference(), target_cap) };
-        
-        if unsafe { self.decrement_ref_cnt() } == 0 {
-            unsafe { dealloc(old_buf, self.0.cap_reference()); }
-        }
-        self.0.set_ptr_reference(alloc);
-        self.0.set_cap_reference(target_cap);
-        self.0.set_len_reference(self.0.wrx_reference());
+        unsafe { self.move_to_alloc(target_cap); }
     }
 
     #[inline]
@@ -241,34 +421,132 @@ GenericBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMA
             self.0.set_wrx(self.0.wrx().min(len));
         }
     }
+
+    /// unlike `Buffer`, small parts aren't moved inline, as each part keeps writing into
+    /// its share of the storage and `unsplit` can only rejoin parts that still share it.
+    fn split_off(&mut self, offset: usize) -> Self {
+        let idx = self.0.rdx() + offset;
+        assert!(self.len() >= idx, "tried splitting buffer with length {} at {}", self.len(), idx);
+        let mut other = self.split_ref();
+        other.advance_view(idx);
+        self.truncate(idx);
+        self.0.set_len(idx);
+        other
+    }
+
+    fn split_to(&mut self, offset: usize) -> Self {
+        let idx = self.0.rdx() + offset;
+        assert!(self.len() >= idx, "tried splitting buffer with length {} at {}", self.len(), idx);
+        let mut other = self.split_ref();
+        other.truncate(idx);
+        other.0.set_len(idx);
+        self.advance_view(idx);
+        other
+    }
+
+    fn split(&mut self) -> Self {
+        self.split_off(0)
+    }
+
+    fn unsplit(&mut self, other: Self) {
+        if self.try_unsplit(other).is_err() {
+            panic!("Unsplitting only works on buffers that are next to each other");
+        }
+    }
+
+    fn try_unsplit(&mut self, other: Self) -> Result<(), Self> {
+        if self.0.flags() != other.0.flags() {
+            return Err(other);
+        }
+        let this = &*self;
+        let (min, max) = if this.0.offset() < other.0.offset() {
+            (this, &other)
+        } else {
+            (&other, this)
+        };
+
+        // check if the left buffer still has uninit data
+        if min.0.wrx() != min.0.len() {
+            return Err(other);
+        }
+
+        let dist = max.0.offset() - min.0.offset();
+        // check if buffers are adjacent
+        if dist != min.0.wrx() {
+            return Err(other);
+        }
+
+        // check if ptrs aren't matching
+        if !self.is_inlined() && min.0.ptr_reference() != max.0.ptr_reference() {
+            return Err(other);
+        }
+
+        // the combined buffer continues reading where the front part left off
+        let rdx = if RETAIN_INDICES { min.0.rdx() } else { 0 };
+        let len = min.0.len() + max.0.len();
+        let offset = min.0.offset();
+        if self.is_inlined() {
+            // inlined parts have their own storage, so the data of the other part has to be copied over
+            unsafe { ptr::copy_nonoverlapping(other.0.ptr_inlined().add(other.0.offset_inlined()), self.0.ptr_inlined().add(other.0.offset_inlined()), other.0.wrx_inlined()); }
+        }
+        self.0.set_len(len);
+        self.0.set_offset(offset);
+        self.0.set_wrx(self.0.wrx() + other.0.wrx());
+        self.0.set_rdx(rdx);
+        Ok(())
+    }
+
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-WritableBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+WritableBuffer for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+
+    #[inline]
+    fn put_slice(&mut self, val: &[u8]) {
+        let ptr = self.ensure_large_enough(val.len());
+        unsafe { ptr::copy_nonoverlapping(val as *const [u8] as *const u8, ptr, val.len()); }
+        self.0.set_wrx(self.0.wrx() + val.len());
+    }
+
+    #[inline]
+    fn put_bytes(&mut self, val: u8, repeat: usize) {
+        let ptr = self.ensure_large_enough(repeat);
+        unsafe { ptr::write_bytes(ptr, val, repeat); }
+        self.0.set_wrx(self.0.wrx() + repeat);
+    }
+
+    #[inline]
+    fn put_u8(&mut self, val: u8) {
+        let ptr = self.ensure_large_enough(1);
+        unsafe { *ptr = val; }
+        self.0.set_wrx(self.0.wrx() + 1);
+    }
+
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+GenericWritableBuffer for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
 
     fn with_capacity(cap: usize) -> Self {
-        if INLINE_SMALL && cap <= INLINE_SIZE {
-            Self(LAYOUT::new_inlined(INLINE_SIZE, 0, [0; 3]))
+        if INLINE_SMALL && cap <= LAYOUT::INLINE_SIZE {
+            Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &[]), PhantomData)
         } else {
+            let len = cap;
             let cap = cap + ADDITIONAL_BUFFER_CAP;
             let alloc = unsafe { alloc_uninit_buffer(cap) };
-            let ret = Self(LAYOUT::new_reference(0, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_reference()));
-            // set ref cnt
-            unsafe { *ret.meta_ptr().cast::<usize>() = 1; }
-            ret
+            // the metadata is only initialized once the buffer gets shared
+            Self(LAYOUT::new_reference(len, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_unique()), PhantomData)
         }
     }
 
     fn zeroed(len: usize) -> Self {
-        if INLINE_SMALL && len <= INLINE_SIZE {
-            Self(LAYOUT::new_inlined(INLINE_SIZE, 0, [0; 3]))
+        if INLINE_SMALL && len <= LAYOUT::INLINE_SIZE {
+            Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &[]), PhantomData)
         } else {
             let cap = len + ADDITIONAL_BUFFER_CAP;
             let alloc = alloc_zeroed_buffer(cap);
-            let ret = Self(LAYOUT::new_reference(len, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_reference()));
-            // set ref cnt
-            unsafe { *ret.meta_ptr().cast::<usize>() = 1; }
-            ret
+            // the metadata is only initialized once the buffer gets shared
+            Self(LAYOUT::new_reference(len, cap, 0, 0, 0, alloc, LAYOUT::FlagsTy::new_unique()), PhantomData)
         }
     }
 
@@ -286,210 +564,122 @@ WritableBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SM
     }
 
     fn reset_writer_index(&mut self) {
-        self.0.set_wrx(0);
-        // maintain rdx
-        self.0.set_rdx(0);
+        if RETAIN_INDICES {
+            // keep the data that was already read, so only the unread data gets discarded
+            self.0.set_wrx(self.0.rdx());
+        } else {
+            self.0.set_wrx(0);
+            self.0.set_rdx(0);
+        }
     }
 
     #[inline]
     fn capacity(&self) -> usize {
-        // for inlined buffers we always have INLINE_SIZE space
-        if self.is_inlined() {
-            return INLINE_SIZE;
-        }
-        self.cap
+        // we treat the len as our cap as that's what is effectively usable for the buffer's user
+        self.0.len()
     }
 
-    #[inline]
-    fn put_slice(&mut self, val: &[u8]) {
-        let ptr = self.ensure_large_enough(val.len());
-        unsafe { ptr::copy_nonoverlapping(val as *const [u8] as *const u8, ptr, val.len()); }
-        self.0.set_len(self.0.len() + val.len());
-    }
+}
 
-    #[inline]
-    fn put_bytes(&mut self, val: u8, repeat: usize) {
-        let ptr = self.ensure_large_enough(repeat);
-        unsafe { ptr::write_bytes(ptr, val, repeat); }
-        self.0.set_len(self.0.len() + repeat);
-    }
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+ReadableBuffer for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
 
     #[inline]
-    fn put_u8(&mut self, val: u8) {
-        let ptr = self.ensure_large_enough(1);
-        unsafe { *ptr = val; }
-        self.0.set_len(self.0.len() + 1);
+    fn advance(&mut self, amount: usize) {
+        let remaining = self.remaining();
+        assert!(amount <= remaining, "tried advancing {} bytes but only {} bytes are left", amount, remaining);
+        self.0.set_rdx(self.0.rdx() + amount);
     }
 
-}
-
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-ReadableBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
     fn remaining(&self) -> usize {
-        let rdx = if STATIC_STORAGE {
-            self.rdx & !STATIC_BUFFER_FLAG
-        } else {
-            self.rdx
-        };
-        self.len() - rdx
+        self.0.wrx() - self.0.rdx()
     }
 
     #[inline]
-    fn split_off(&mut self, offset: usize) -> Self {
-        let idx = self.get_rdx() + offset;
-        assert!(self.len() > idx, "tried splitting buffer with length {} at {}", self.len, idx);
-        let mut other = self.clone();
-
-        self.set_len(idx);
-        other.set_rdx(idx);
-
-        other
-    }
-
-    fn split_to(&mut self, offset: usize) -> Self {
-        let idx = self.get_rdx() + offset;
-        assert!(self.len() > idx, "tried splitting buffer with length {} at {}", self.len, idx);
-        let mut other = self.clone();
-
-        other.set_len(idx);
-        self.set_rdx(idx);
-
-        other
+    fn get_u8(&mut self) -> u8 {
+        let ptr = self.ensure_readable(1);
+        self.0.set_rdx(self.0.rdx() + 1);
+        unsafe { *ptr }
     }
 
-    fn split(&mut self) -> Self {
-        // TODO: check if the panic check gets removed
-        self.split_off(0)
+    #[inline]
+    fn copy_to_slice(&mut self, dst: &mut [u8]) {
+        dst.copy_from_slice(self.get_slice(dst.len()));
     }
 
-    fn unsplit(&mut self, other: Self) {
-        if self.is_empty() {
-            *self = other;
-            return;
-        }
-
-        if self.is_static() {
-            if !other.is_static() {
-                panic!("Static buffers can only be merged with other static buffers");
-            }
-            if self.ptr != other.ptr {
-                panic!("Unsplitting only works on buffers that have the same src");
-            }
-            if self.get_rdx() + self.len() != other.get_rdx() && self.len() != other.get_rdx() + other.len() {
-                panic!("Unsplitting only works on buffers that are next to each other");
-            }
-            self.set_rdx(self.get_rdx().min(other.get_rdx()));
-            self.set_len(self.len() + other.len());
-            return;
-        }
+}
 
-        if self.is_inlined() {
-            if !other.is_inlined() {
-                panic!("Inlined buffers can only be merged with other inlined buffers");
-            }
-            if self.remaining() + other.remaining() > INLINE_SIZE {
-                panic!("Unsplitting inlined buffers only works if they are small enough");
-            }
-            if self.get_rdx() + self.len() != other.get_rdx() && self.get_rdx() != other.get_rdx() + other.len() {
-                panic!("Unsplitting only works on buffers that are next to each other");
-            }
-            self.set_rdx(self.get_rdx().min(other.get_rdx()));
-            self.set_len(self.len().max(other.len()));
-            return;
-        }
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+GenericReadableBuffer for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
 
-        if self.ptr != other.ptr {
-            panic!("Unsplitting only works on buffers that have the same src");
-        }
-        if self.get_rdx() + self.len() != other.get_rdx() && self.get_rdx() != other.get_rdx() + other.len() {
-            panic!("Unsplitting only works on buffers that are next to each other");
-        }
-        self.set_rdx(self.get_rdx().min(other.get_rdx()));
-        self.set_len(self.len().max(other.len()));
+    #[inline]
+    fn reset_reader_index(&mut self) {
+        self.0.set_rdx(0);
     }
 
     #[inline]
-    fn get_bytes(&mut self, bytes: usize) -> &[u8] {
+    fn get_slice(&mut self, bytes: usize) -> &[u8] {
         let ptr = self.ensure_readable(bytes);
-        self.rdx += bytes;
+        self.0.set_rdx(self.0.rdx() + bytes);
         unsafe { &*slice_from_raw_parts(ptr, bytes) }
     }
 
-    #[inline]
-    fn get_u8(&mut self) -> u8 {
-        let ptr = self.ensure_readable(1);
-        self.rdx += 1;
-        unsafe { *ptr }
-    }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-RWBuffer for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {}
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+RWBuffer for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {}
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Drop for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+Drop for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
     fn drop(&mut self) {
         if self.is_inlined() {
             // we don't need to do anything for inlined buffers
             return;
         }
-        if self.is_static() {
-            // we don't need to do anything for static buffers
-            return;
-        }
-        if !INLINE_SMALL && !STATIC_STORAGE && self.ptr == empty_sentinel() {
-            // we don't do anything for empty buffers
-            return;
-        }
-        // fast path for single ref cnt scenarios
-        if unsafe { self.is_only() } {
-            unsafe { dealloc(self.ptr, self.cap); }
-            return;
-        }
-        let meta_ptr = unsafe { self.meta_ptr() };
-        let ref_cnt = unsafe { &*meta_ptr.cast::<AtomicUsize>() };
-        let remaining = ref_cnt.fetch_sub(1, Ordering::AcqRel) - 1; // TODO: can we choose a weaker ordering?
-        if remaining == 0 {
-            unsafe { dealloc(self.ptr, self.cap); }
-        }
+        unsafe { self.release_alloc(); }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Clone for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+Clone for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
     fn clone(&self) -> Self {
-        if self.is_inlined() || self.is_static() {
-            return Self(self.0.clone());
+        if self.is_unique() {
+            if !self.has_meta_room() {
+                // the data can't be moved behind a shared reference, so the clone gets its own allocation
+                let cap = self.0.len_reference() + ADDITIONAL_BUFFER_CAP;
+                let alloc = unsafe { realloc_buffer(self.0.ptr_reference(), self.0.offset_reference(), self.0.wrx_reference(), cap) };
+                return Self(LAYOUT::new_reference(self.0.len_reference(), cap, self.0.wrx_reference(), self.0.rdx_reference(), 0, alloc, LAYOUT::FlagsTy::new_unique()), PhantomData);
+            }
+            // the metadata is only initialized once the allocation gets shared, other threads
+            // may clone the buffer at the same time, so only one of them sets it up
+            with_share_lock(|| if self.is_unique() {
+                unsafe { self.init_shared(); }
+            });
         }
-
-        // we can't just increment reference count as that would allow for
-        // multiple mutable references to the same memory location
-
-        let alloc = unsafe { realloc_buffer_counted(self.0.ptr_reference(), self.0.offset_reference(), self.0.len_reference(), self.0.cap_reference()) };
-
-        Self(LAYOUT::new_reference(self.0.len_reference(), self.0.cap_reference(), self.0.wrx_reference(), self.0.rdx_reference(), self.0.offset_reference(), self.0.ptr_reference(), self.0.flags()))
+        if !self.is_inlined() {
+            // synchronize with the thread which set up the metadata
+            fence(Ordering::Acquire);
+            // the allocation is shared until one of the buffers gets written to
+            unsafe { self.increment_ref_cnt(); }
+        }
+        Self(self.0.clone(), PhantomData)
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-AsRef<[u8]> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+AsRef<[u8]> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
     fn as_ref(&self) -> &[u8] {
-        let ptr = if self.is_inlined() {
-            unsafe { self.inlined_buffer_ptr() }
-        } else {
-            self.ptr
-        };
-        let ptr = unsafe { ptr.add(self.rdx) };
+        let ptr = unsafe { self.0.ptr().add(self.0.offset() + self.0.rdx()) };
         unsafe { &*slice_from_raw_parts(ptr, self.remaining()) }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Deref for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+Deref for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     type Target = [u8];
 
     #[inline]
@@ -498,181 +688,158 @@ Deref for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STAT
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Borrow<[u8]> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+Borrow<[u8]> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
     fn borrow(&self) -> &[u8] {
         self.as_ref()
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Default for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+Default for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-From<&'static [u8]> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<&'static [u8]> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
     fn from(value: &'static [u8]) -> Self {
-        Self(LAYOUT::new_reference(value.len(), value.len(), value.len(), 0, 0, value.as_ptr().cast_mut(), Flags::new_static_reference()))
+        Self(LAYOUT::new_reference(value.len(), value.len(), value.len(), 0, 0, value.as_ptr().cast_mut(), LAYOUT::FlagsTy::new_static_reference()), PhantomData)
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Into<Vec<u8>> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
-    #[inline]
-    fn into(self) -> Vec<u8> {
-        if self.is_inlined() {
-            let alloc = unsafe { realloc_buffer(self.inlined_buffer_ptr(), self.len(), self.len()) }; // FIXME: should we add ADDITIONAL_BUFFER_CAP?
-            return unsafe { Vec::from_raw_parts(alloc, self.len(), self.len()) };
-        }
-        if self.is_static() {
-            let cap = self.len + ADDITIONAL_BUFFER_CAP;
-            let alloc = unsafe { realloc_buffer(self.ptr, self.len, cap) };
-            return unsafe { Vec::from_raw_parts(alloc, self.len, cap) };
-        }
-        if unsafe { self.is_only() } {
-            let ret = unsafe { Vec::from_raw_parts(self.ptr, self.len, self.cap) };
-            mem::forget(self);
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES>> for Vec<u8> {
+    #[inline]
+    fn from(buffer: BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES>) -> Self {
+        if buffer.is_inlined() {
+            let alloc = unsafe { realloc_buffer(buffer.0.ptr_inlined(), buffer.0.offset_inlined(), buffer.len(), buffer.len()) }; // FIXME: should we add ADDITIONAL_BUFFER_CAP?
+            stats::on_hand_over(buffer.len());
+            stats::on_conversion_copy();
+            return unsafe { Vec::from_raw_parts(alloc, buffer.len(), buffer.len()) };
+        }
+        let this = ManuallyDrop::new(buffer);
+        if this.is_unique() {
+            // unique allocations don't need any checks before being reused
+            if this.0.offset_reference() == 0 {
+                stats::on_hand_over(this.0.cap_reference());
+                return unsafe { Vec::from_raw_parts(this.0.ptr_reference(), this.0.wrx_reference(), this.0.cap_reference()) };
+            }
+            let data = unsafe { &*slice_from_raw_parts(this.0.ptr_reference().add(this.0.offset_reference()), this.0.wrx_reference()) };
+            let ret = data.to_vec();
+            stats::on_conversion_copy();
+            unsafe { this.release_alloc(); }
             return ret;
         }
-        // TODO: should we try to shrink?
-        let buf = unsafe { realloc_buffer(self.ptr, self.len, self.cap) };
-        unsafe { Vec::from_raw_parts(buf, self.len, self.cap) }
+        // the storage decides whether it can be reused, this consumes our reference
+        let vtable = unsafe { this.vtable() };
+        unsafe { (vtable.to_vec)(this.0.ptr_reference(), this.0.cap_reference(), this.0.offset_reference(), this.0.wrx_reference()) }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-From<Vec<u8>> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<Vec<u8>> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+    /// Adopts the vec's allocation without touching its spare capacity until the buffer gets
+    /// shared. Vecs without room for the metadata are only moved at that point or once the
+    /// buffer grows.
     #[inline]
     fn from(mut value: Vec<u8>) -> Self {
         let ptr = value.as_mut_ptr();
         let cap = value.capacity();
         let len = value.len();
         // handle small buffers
-        if INLINE_SMALL && len <= INLINE_SIZE {
+        if INLINE_SMALL && len <= LAYOUT::INLINE_SIZE {
             // FIXME: should we instead keep the small buffer if it exists already and doesn't cost us anything?
-            let mut ret = Self {
-                len: len | INLINE_BUFFER_FLAG,
-                rdx: 0,
-                ptr: null_mut(),
-                cap: 0,
-            };
-            unsafe { ptr::copy_nonoverlapping(ptr, ret.inlined_buffer_ptr(), len); }
+            let mut ret = Self(LAYOUT::new_inlined(LAYOUT::INLINE_SIZE, 0, &value), PhantomData);
+            ret.0.set_wrx_inlined(len);
             return ret;
         }
+        if cap == 0 {
+            // the vec didn't allocate, so there is nothing to adopt
+            return Self::new();
+        }
         mem::forget(value);
-        // reuse existing buffer
-        let ret = Self {
-            len,
-            rdx: 0,
-            ptr,
-            cap,
+        stats::on_adopt(cap);
+        // the spare capacity in front of the metadata can be written to
+        let writable = if fits_meta(ptr, len, cap) {
+            len.max(cap - ADDITIONAL_BUFFER_CAP)
+        } else {
+            len
         };
-        // set ref cnt
-        unsafe { *ret.meta_ptr().cast::<usize>() = 1; }
-        ret
+        Self(LAYOUT::new_reference(writable, cap, len, 0, 0, ptr, LAYOUT::FlagsTy::new_unique()), PhantomData)
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR_OTHER: usize, const INITIAL_CAP_OTHER: usize, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-From<BufferGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE>> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
-    #[inline]
-    fn from(value: BufferGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE>) -> Self {
-        if value.is_inlined() || value.is_static() {
-            return Self {
-                len: value.len,
-                rdx: value.rdx,
-                ptr: value.ptr,
-                cap: 0,
-            };
-        }
-        if unsafe { value.is_only() } {
-            let ret = Self {
-                len: value.len,
-                rdx: value.rdx,
-                ptr: value.ptr,
-                cap: value.capacity(),
-            };
-            mem::forget(value);
-            return ret;
-        }
-        // TODO: should we try to shrink?
-        let cap = value.capacity();
-        let alloc = unsafe { realloc_buffer_counted(value.ptr, value.len, cap) };
-        Self {
-            len: value.len,
-            rdx: value.rdx,
-            ptr: alloc,
-            cap,
-        }
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<Box<[u8]>> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: Box<[u8]>) -> Self {
+        Self::from(Vec::from(value))
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR_OTHER: usize, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Into<BufferGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL>> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
-    #[inline]
-    fn into(self) -> BufferGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER> {
-        if self.is_inlined() || self.is_static() {
-            return BufferGeneric {
-                len: self.len,
-                rdx: self.rdx,
-                ptr: self.ptr,
-                cap: self.cap,
-            };
-        }
-        if unsafe { self.is_only() } {
-            let ret = BufferGeneric {
-                len: self.len,
-                rdx: self.rdx,
-                cap: self.cap,
-                ptr: self.ptr,
-            };
-            mem::forget(self);
-            return ret;
-        }
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<String> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: String) -> Self {
+        Self::from(value.into_bytes())
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<&'static str> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: &'static str) -> Self {
+        Self::from(value.as_bytes())
+    }
+}
 
-        // TODO: should we try to shrink?
-        let alloc = unsafe { realloc_buffer_counted(self.ptr, self.len, self.cap) };
-        BufferGeneric {
-            len: self.len,
-            rdx: self.rdx,
-            cap: self.cap,
-            ptr: alloc,
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<Cow<'static, [u8]>> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+    #[inline]
+    fn from(value: Cow<'static, [u8]>) -> Self {
+        match value {
+            Cow::Borrowed(value) => Self::from(value),
+            Cow::Owned(value) => Self::from(value),
         }
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR_OTHER: usize, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-From<BufferMutGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL>> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<Arc<[u8]>> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
     #[inline]
-    fn from(value: BufferMutGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>) -> Self {
-        let ret = Self {
-            len: value.len,
-            rdx: 0,
-            ptr: value.ptr,
-            cap: value.cap,
-        };
-        mem::forget(value);
-        ret
+    fn from(value: Arc<[u8]>) -> Self {
+        // the data may be shared with others, so we have to copy it
+        Self::from(value.to_vec())
     }
 }
 
-impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, const GROWTH_FACTOR_OTHER: usize, const INITIAL_CAP_OTHER: usize, const GROWTH_FACTOR: usize, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
-Into<BufferMutGeneric<LAYOUT, GROWTH_FACTOR_OTHER, INITIAL_CAP_OTHER, INLINE_SMALL>> for BufferRWGeneric<LAYOUT, GROWTH_FACTOR, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherLayout: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, const COMPACT_DIVISOR_OTHER: usize, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<BufferGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER, COMPACT_DIVISOR_OTHER>> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+    /// Reuses the storage of the buffer and carries over its indices according to
+    /// `RETAIN_INDICES`, external storage is copied as read-write buffers don't support it.
+    /// Inlined data is only copied into an allocation if it doesn't fit into the inline storage of this buffer.
     #[inline]
-    fn into(self) -> Self {
-        let ret = BufferMutGeneric {
-            len: self.len,
-            ptr: self.ptr,
-            cap: self.cap,
-            offset: self.cap,
+    fn from(value: BufferGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES_OTHER, COMPACT_DIVISOR_OTHER>) -> Self {
+        let layout = if value.is_external() {
+            value.into_owned_layout()
+        } else {
+            value.into_layout()
         };
-        mem::forget(self);
-        ret
+        Self(convert_layout::<INLINE_SMALL, STATIC_STORAGE, STATIC_STORAGE, _, _>(layout, ADDITIONAL_BUFFER_CAP), PhantomData).adopt_indices()
+    }
+}
+
+impl<LAYOUT: BufferFormat<INLINE_SMALL, STATIC_STORAGE>, OtherLayout: BufferFormat<INLINE_SMALL, false>, OtherGrowth: GrowthPolicy, const INITIAL_CAP_OTHER: usize, const RETAIN_INDICES_OTHER: bool, GROWTH: GrowthPolicy, const INITIAL_CAP: usize, const INLINE_SMALL: bool, const STATIC_STORAGE: bool, const RETAIN_INDICES: bool>
+From<BufferMutGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>> for BufferRWGeneric<LAYOUT, GROWTH, INITIAL_CAP, INLINE_SMALL, STATIC_STORAGE, RETAIN_INDICES> {
+    /// Reuses the allocation of the mutable buffer, its indices are carried over according to `RETAIN_INDICES`.
+    /// Inlined data is only copied into an allocation if it doesn't fit into the inline storage of this buffer.
+    #[inline]
+    fn from(value: BufferMutGeneric<OtherLayout, OtherGrowth, INITIAL_CAP_OTHER, INLINE_SMALL, RETAIN_INDICES_OTHER>) -> Self {
+        Self(convert_layout::<INLINE_SMALL, false, STATIC_STORAGE, _, _>(value.into_layout(), ADDITIONAL_BUFFER_CAP), PhantomData).adopt_indices()
     }
 }
@@ -0,0 +1,189 @@
+use std::mem::size_of;
+use crate::buffer::{COMPACT_DIVISOR_DEFAULT, INITIAL_CAP_DEFAULT};
+use crate::buffer_format::BufferFormat;
+use crate::buffer_format::extended::FormatExtended;
+use crate::buffer_format::half::FormatHalf;
+use crate::buffer_format::packed::FormatPacked;
+use crate::growth::{Doubling, GrowthPolicy};
+use crate::ref_cnt::AtomicRefCnt;
+
+/// Bundles all the parameters of the buffer types, so they can be named
+/// instead of being passed positionally.
+///
+/// The buffer types don't take a config directly: their formats are bounded by
+/// `BufferFormat<INLINE_SMALL, STATIC_STORAGE>`, and naming `C::INLINE_SMALL` in that
+/// bound requires `generic_const_exprs`, which isn't stable yet. Buffer types for a
+/// config are declared through `buffer_types!` instead, which reads the parameters
+/// from the config and passes them on in the right order.
+///
+/// Buffers always allocate through the global allocator, so a config doesn't pick one.
+pub trait BufferConfig {
+    /// The format used to store the buffer's indices and flags.
+    type Layout: ConfigFormat;
+    /// The format of read-only buffers, they don't have to support writing
+    /// so they can use a format with a larger inline storage.
+    type BufferLayout: ConfigFormat;
+    /// How the capacity of buffers grows once they run out of space.
+    type Growth: GrowthPolicy;
+    const INITIAL_CAP: usize;
+    /// Whether small buffers are stored inline instead of being allocated.
+    const INLINE_SMALL: bool;
+    /// Whether buffers may reference static or external storage, this doesn't
+    /// apply to mutable buffers.
+    const STATIC_STORAGE: bool;
+    /// See [`GenericBuffer`](crate::GenericBuffer) for what this controls.
+    const RETAIN_INDICES: bool;
+    /// See [`BufferGeneric`](crate::buffer::BufferGeneric) for what this controls, this only applies to read-only buffers.
+    const COMPACT_DIVISOR: usize;
+}
+
+/// A format that supports every combination of the `INLINE_SMALL` and `STATIC_STORAGE`
+/// settings, so a config can't pick settings its formats don't implement.
+pub trait ConfigFormat: BufferFormat<true, true> + BufferFormat<true, false> + BufferFormat<false, true> + BufferFormat<false, false> {}
+
+impl<T: BufferFormat<true, true> + BufferFormat<true, false> + BufferFormat<false, true> + BufferFormat<false, false>> ConfigFormat for T {}
+
+/// The config of `Buffer`, `BufferMut` and `BufferRW`.
+pub struct DefaultConfig;
+
+impl BufferConfig for DefaultConfig {
+    type Layout = FormatHalf;
+    type BufferLayout = FormatPacked;
+    type Growth = Doubling;
+    const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT;
+    const INLINE_SMALL: bool = true;
+    const STATIC_STORAGE: bool = true;
+    const RETAIN_INDICES: bool = true;
+    const COMPACT_DIVISOR: usize = COMPACT_DIVISOR_DEFAULT;
+}
+
+/// Like `DefaultConfig` but always allocates, so the data of buffers never moves
+/// when they grow past the inline size.
+pub struct NoInlineConfig;
+
+impl BufferConfig for NoInlineConfig {
+    type Layout = FormatHalf;
+    type BufferLayout = FormatPacked;
+    type Growth = Doubling;
+    const INITIAL_CAP: usize = INITIAL_CAP_DEFAULT;
+    const INLINE_SMALL: bool = false;
+    const STATIC_STORAGE: bool = true;
+    const RETAIN_INDICES: bool = true;
+    const COMPACT_DIVISOR: usize = COMPACT_DIVISOR_DEFAULT;
+}
+
+/// A config for buffers that may grow past the capacity `FormatHalf` supports.
+pub struct LargeConfig;
+
+impl BufferConfig for LargeConfig {
+    type Layout = FormatExtended;
+    type BufferLayout = FormatExtended;
+    type Growth = Doubling;
+    const INITIAL_CAP: usize = 4096;
+    const INLINE_SMALL: bool = true;
+    const STATIC_STORAGE: bool = true;
+    const RETAIN_INDICES: bool = true;
+    const COMPACT_DIVISOR: usize = COMPACT_DIVISOR_DEFAULT;
+}
+
+/// Like `DefaultConfig` but stores up to `INLINE_WORDS` words inline instead of 3,
+/// this makes the buffers larger but avoids allocating for medium sized data.
+///
+/// ```
+/// use buffer::buffer_types;
+/// use buffer::config::WideInlineConfig;
+/// use buffer::{GenericBuffer, WritableBuffer};
+///
+/// // stores up to 64 bytes inline on 64 bit systems
+/// buffer_types!(WideInlineConfig<8>, WideBuffer, WideBufferMut, WideBufferRW);
+///
+/// let mut buffer = WideBufferMut::new();
+/// buffer.put_slice(&[1; 64]);
+/// assert_eq!(WideBuffer::from(buffer).len(), 64);
+/// ```
+pub struct WideInlineConfig<const INLINE_WORDS: usize>;
+
+impl<const INLINE_WORDS: usize> BufferConfig for WideInlineConfig<INLINE_WORDS> {
+    type Layout = FormatHalf<AtomicRefCnt, INLINE_WORDS>;
+    type BufferLayout = FormatHalf<AtomicRefCnt, INLINE_WORDS>;
+    type Growth = Doubling;
+    const INITIAL_CAP: usize = (2 * INLINE_WORDS * size_of::<usize>()).next_power_of_two();
+    const INLINE_SMALL: bool = true;
+    const STATIC_STORAGE: bool = true;
+    const RETAIN_INDICES: bool = true;
+    const COMPACT_DIVISOR: usize = COMPACT_DIVISOR_DEFAULT;
+}
+
+/// Declares aliases for the buffer types that use the given config.
+///
+/// ```
+/// use buffer::buffer_types;
+/// use buffer::config::LargeConfig;
+/// use buffer::{GenericBuffer, GenericWritableBuffer};
+///
+/// buffer_types!(LargeConfig, MyBuffer, MyBufferMut, MyBufferRW);
+///
+/// let buffer = MyBufferRW::with_capacity(16);
+/// assert!(MyBuffer::from(buffer).is_empty());
+/// ```
+#[macro_export]
+macro_rules! buffer_types {
+    ($config:ty, $buffer:ident, $buffer_mut:ident, $buffer_rw:ident) => {
+        $crate::buffer_types!(@buffer $config, $buffer);
+        $crate::buffer_types!(@mut $config, $buffer_mut);
+        $crate::buffer_types!(@rw $config, $buffer_rw);
+    };
+    (@buffer $config:ty, $buffer:ident) => {
+        pub type $buffer = $crate::buffer::BufferGeneric<
+            <$config as $crate::config::BufferConfig>::BufferLayout,
+            <$config as $crate::config::BufferConfig>::Growth,
+            { <$config as $crate::config::BufferConfig>::INITIAL_CAP },
+            { <$config as $crate::config::BufferConfig>::INLINE_SMALL },
+            { <$config as $crate::config::BufferConfig>::STATIC_STORAGE },
+            { <$config as $crate::config::BufferConfig>::RETAIN_INDICES },
+            { <$config as $crate::config::BufferConfig>::COMPACT_DIVISOR },
+        >;
+    };
+    (@weak $config:ty, $weak:ident) => {
+        pub type $weak = $crate::buffer::WeakBufferGeneric<
+            <$config as $crate::config::BufferConfig>::BufferLayout,
+            <$config as $crate::config::BufferConfig>::Growth,
+            { <$config as $crate::config::BufferConfig>::INITIAL_CAP },
+            { <$config as $crate::config::BufferConfig>::INLINE_SMALL },
+            { <$config as $crate::config::BufferConfig>::STATIC_STORAGE },
+            { <$config as $crate::config::BufferConfig>::RETAIN_INDICES },
+            { <$config as $crate::config::BufferConfig>::COMPACT_DIVISOR },
+        >;
+    };
+    (@mut $config:ty, $buffer_mut:ident) => {
+        pub type $buffer_mut = $crate::buffer_mut::BufferMutGeneric<
+            <$config as $crate::config::BufferConfig>::Layout,
+            <$config as $crate::config::BufferConfig>::Growth,
+            { <$config as $crate::config::BufferConfig>::INITIAL_CAP },
+            { <$config as $crate::config::BufferConfig>::INLINE_SMALL },
+            { <$config as $crate::config::BufferConfig>::RETAIN_INDICES },
+        >;
+    };
+    (@reverse $config:ty, $reverse:ident) => {
+        pub type $reverse = $crate::buffer_reverse::ReverseBufferMutGeneric<
+            <$config as $crate::config::BufferConfig>::Layout,
+            <$config as $crate::config::BufferConfig>::Growth,
+            { <$config as $crate::config::BufferConfig>::INITIAL_CAP },
+            { <$config as $crate::config::BufferConfig>::INLINE_SMALL },
+            { <$config as $crate::config::BufferConfig>::RETAIN_INDICES },
+        >;
+    };
+    (@rw $config:ty, $buffer_rw:ident) => {
+        pub type $buffer_rw = $crate::buffer_rw::BufferRWGeneric<
+            <$config as $crate::config::BufferConfig>::Layout,
+            <$config as $crate::config::BufferConfig>::Growth,
+            { <$config as $crate::config::BufferConfig>::INITIAL_CAP },
+            { <$config as $crate::config::BufferConfig>::INLINE_SMALL },
+            { <$config as $crate::config::BufferConfig>::STATIC_STORAGE },
+            { <$config as $crate::config::BufferConfig>::RETAIN_INDICES },
+        >;
+    };
+}
+
+buffer_types!(NoInlineConfig, NoInlineBuffer, NoInlineBufferMut, NoInlineBufferRW);
+buffer_types!(LargeConfig, LargeBuffer, LargeBufferMut, LargeBufferRW);
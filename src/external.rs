@@ -0,0 +1,105 @@
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize};
+use crate::ref_cnt::RefCnt;
+use crate::storage::{StorageVTable, VecStorage};
+use crate::util::Metadata;
+
+/// The header of buffers whose data is owned by something other than the buffer
+/// itself, e.g. a memory mapped file.
+/// The buffer's reference pointer points to this header instead of the data
+/// and the metadata at its start holds the buffer's reference count.
+///
+/// Each header carries the operations of its storage, so every kind of owner
+/// can decide for itself e.g. whether its data can be handed out as a vec.
+#[repr(C)]
+pub(crate) struct ExternalHeader {
+    pub(crate) meta: Metadata,
+    pub(crate) data: *mut u8,
+    pub(crate) len: usize,
+    /// releases the external storage and the header itself.
+    release: unsafe fn(*mut ExternalHeader),
+    /// the operations buffers referencing this header use, these get passed the header.
+    pub(crate) vtable: &'static StorageVTable,
+}
+
+impl ExternalHeader {
+
+    #[inline]
+    pub(crate) fn new(data: *mut u8, len: usize, release: unsafe fn(*mut ExternalHeader), vtable: &'static StorageVTable) -> Self {
+        Self {
+            meta: Metadata {
+                ref_cnt: AtomicUsize::new(1),
+                align: 1,
+                weak: AtomicPtr::new(ptr::null_mut()),
+                overlapping: AtomicBool::new(false),
+            },
+            data,
+            len,
+            release,
+            vtable,
+        }
+    }
+
+    /// SAFETY: `header` has to point to a live header.
+    #[inline]
+    pub(crate) unsafe fn data(header: *mut u8) -> *mut u8 {
+        unsafe { (*header.cast::<ExternalHeader>()).data }
+    }
+
+    /// SAFETY: `header` has to point to a live header.
+    #[inline]
+    pub(crate) unsafe fn vtable(header: *mut u8) -> &'static StorageVTable {
+        unsafe { (*header.cast::<ExternalHeader>()).vtable }
+    }
+
+    /// releases the external storage, this has to be called once the ref cnt drops to 0.
+    ///
+    /// SAFETY: `header` has to point to a live header which may not be used afterwards.
+    #[inline]
+    pub(crate) unsafe fn release(header: *mut ExternalHeader) {
+        // make sure weak buffers can't observe the storage anymore
+        unsafe { (*header).meta.detach_weak(); }
+        let release = unsafe { (*header).release };
+        unsafe { release(header); }
+    }
+
+}
+
+/// The header of buffers which keep an arbitrary owner of their data alive.
+#[repr(C)]
+struct OwnerHeader<T> {
+    header: ExternalHeader,
+    owner: T,
+}
+
+/// moves `owner` into a side allocation and returns its header, buffers referencing
+/// the header use `vtable` to operate on it.
+pub(crate) fn owner_header<T: AsRef<[u8]> + Send + Sync + 'static>(owner: T, vtable: &'static StorageVTable) -> *mut ExternalHeader {
+    let mut boxed = Box::new(OwnerHeader {
+        header: ExternalHeader::new(ptr::null_mut(), 0, release_owner::<T>, vtable),
+        owner,
+    });
+    // the owner's data stays at the same location from here on as the owner isn't moved anymore
+    let data = boxed.owner.as_ref();
+    boxed.header.data = data.as_ptr().cast_mut();
+    boxed.header.len = data.len();
+    Box::into_raw(boxed).cast::<ExternalHeader>()
+}
+
+unsafe fn release_owner<T>(header: *mut ExternalHeader) {
+    drop(unsafe { Box::from_raw(header.cast::<OwnerHeader<T>>()) });
+}
+
+/// moves `vec` into a side allocation and returns its header, the vec can
+/// be taken out again once the storage is uniquely owned.
+pub(crate) fn vec_header<RC: RefCnt>(vec: Vec<u8>) -> *mut ExternalHeader {
+    owner_header(vec, VecStorage::<RC>::VTABLE)
+}
+
+/// takes the vec out of a header created by `vec_header` and releases the header.
+///
+/// SAFETY: `header` has to be created by `vec_header` and may not be used afterwards.
+pub(crate) unsafe fn take_vec(header: *mut ExternalHeader) -> Vec<u8> {
+    let header = unsafe { Box::from_raw(header.cast::<OwnerHeader<Vec<u8>>>()) };
+    header.owner
+}
@@ -0,0 +1,87 @@
+/// Decides how much the capacity of a buffer grows once it runs out of space.
+pub trait GrowthPolicy {
+    /// returns the capacity a buffer with a capacity of `curr` bytes grows to
+    /// when it needs at least `req` bytes, this has to be at least `req`.
+    fn grow(curr: usize, req: usize) -> usize;
+}
+
+/// returns `curr` if it's sufficient to hold `req` bytes and otherwise the
+/// capacity `G` grows to.
+#[inline]
+pub(crate) fn sufficient_cap<G: GrowthPolicy>(curr: usize, req: usize) -> usize {
+    if curr >= req {
+        return curr;
+    }
+    G::grow(curr, req)
+}
+
+/// Doubles the capacity.
+pub struct Doubling;
+
+impl GrowthPolicy for Doubling {
+    #[inline]
+    fn grow(curr: usize, req: usize) -> usize {
+        curr.saturating_mul(2).max(req)
+    }
+}
+
+/// Grows the capacity by half of its size, this wastes less memory than
+/// doubling at the cost of reallocating more often.
+pub struct OneAndHalf;
+
+impl GrowthPolicy for OneAndHalf {
+    #[inline]
+    fn grow(curr: usize, req: usize) -> usize {
+        curr.saturating_add(curr / 2).max(req)
+    }
+}
+
+/// returns the page size of the system, on systems it isn't queried on or if querying
+/// it fails this assumes the common page size of 4096 bytes.
+#[inline]
+fn page_size() -> usize {
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+    return crate::mmap::page_size().unwrap_or(4096);
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios")))]
+    4096
+}
+
+/// Doubles the capacity and rounds it up to a multiple of the page size.
+pub struct PageRounded;
+
+impl GrowthPolicy for PageRounded {
+    #[inline]
+    fn grow(curr: usize, req: usize) -> usize {
+        let cap = Doubling::grow(curr, req);
+        cap.checked_next_multiple_of(page_size()).unwrap_or(cap)
+    }
+}
+
+/// Grows the capacity in steps of `INCREMENT` bytes, by at least one step.
+pub struct FixedIncrement<const INCREMENT: usize>;
+
+impl<const INCREMENT: usize> GrowthPolicy for FixedIncrement<INCREMENT> {
+    #[inline]
+    fn grow(curr: usize, req: usize) -> usize {
+        if INCREMENT == 0 {
+            return req;
+        }
+        let steps = req.saturating_sub(curr).div_ceil(INCREMENT).max(1);
+        curr.saturating_add(steps.saturating_mul(INCREMENT)).max(req)
+    }
+}
+
+/// Doubles the capacity until it reaches `LIMIT` bytes, from there on
+/// it grows in steps of `LIMIT` bytes.
+pub struct DoublingThenLinear<const LIMIT: usize>;
+
+impl<const LIMIT: usize> GrowthPolicy for DoublingThenLinear<LIMIT> {
+    #[inline]
+    fn grow(curr: usize, req: usize) -> usize {
+        if curr < LIMIT {
+            Doubling::grow(curr, req)
+        } else {
+            FixedIncrement::<LIMIT>::grow(curr, req)
+        }
+    }
+}
@@ -4,10 +4,30 @@ use std::ops::{Deref, RangeBounds};
 pub mod buffer;
 pub mod buffer_rw;
 pub mod buffer_mut;
+pub mod buffer_reverse;
+pub mod buffer_array;
+pub mod config;
+pub mod growth;
+pub mod ring;
+pub mod stats;
 mod util;
 mod buffer_layout;
 mod buffer_format;
-
+mod ref_cnt;
+mod external;
+mod storage;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))]
+mod mmap;
+
+/// All buffer types have a `RETAIN_INDICES` parameter which decides whether operations
+/// that could either keep or reset a buffer's indices keep them:
+/// - conversions between buffer types keep the reader index or reset it to 0.
+/// - `unsplit` keeps the reader index of the front part or resets it to 0.
+/// - `reset_writer_index` resets the writer index to the reader index, so data that was
+///   already read is kept, or resets both indices to 0.
+/// - `clear` keeps the position of the buffer's view within its storage or moves it back
+///   to the start of the storage if it isn't shared, reclaiming the space in front of it.
+///   Read-only buffers release their storage instead.
 pub trait GenericBuffer: Clone + AsRef<[u8]> + Deref<Target = [u8]> + Borrow<[u8]> + Into<Vec<u8>> + From<Vec<u8>> {
 
     /// creates a new empty instance of a buffer
@@ -54,7 +74,8 @@ pub trait GenericBuffer: Clone + AsRef<[u8]> + Deref<Target = [u8]> + Borrow<[u8
     /// this will merge the current view and another view that was previously split off
     /// from it. Note that this will only work on buffers that weren't modified in a way
     /// that caused reallocation after splitting. Note, that the inactive index
-    /// (opposite of the type-dependent index specified above) will be reset to the beginning of the comboned buffer.
+    /// (opposite of the type-dependent index specified above) will be reset to the beginning of the comboned buffer
+    /// unless `RETAIN_INDICES` is set, in which case the reader index of the front part is kept.
     /// 
     /// #panic
     /// panics on failure to merge buffers as described above.
@@ -63,23 +84,17 @@ pub trait GenericBuffer: Clone + AsRef<[u8]> + Deref<Target = [u8]> + Borrow<[u8
     /// this will merge the current view and another view that was previously split off
     /// from it. Note that this will only work on buffers that weren't modified in a way
     /// that caused reallocation after splitting. Note, that the inactive index
-    /// (opposite of the type-dependent index specified above) will be reset to the beginning of the comboned buffer.
+    /// (opposite of the type-dependent index specified above) will be reset to the beginning of the comboned buffer
+    /// unless `RETAIN_INDICES` is set, in which case the reader index of the front part is kept.
     fn try_unsplit(&mut self, other: Self) -> Result<(), Self>;
 
 }
 
-pub trait ReadableBuffer: GenericBuffer + From<&'static [u8]> {
-
-    #[inline]
-    fn from_static(buf: &'static [u8]) -> Self {
-        <Self as From<&'static [u8]>>::from(buf)
-    }
+/// Reading access to a sequence of bytes, this is implemented by the buffers that
+/// can be read from and by the consuming half of a `BufferRing`.
+pub trait ReadableBuffer {
 
-    /// Resets the reader index to 0 so the buffer can be read
-    /// from the beginning again.
-    fn reset_reader_index(&mut self);
-
-    /// Advanced the reader index by `amount`
+    /// Advances the reader index by `amount`
     /// 
     /// #Panic
     /// May panic when amount exceeds `remaining()`.
@@ -89,20 +104,26 @@ pub trait ReadableBuffer: GenericBuffer + From<&'static [u8]> {
     /// read from this buffer
     fn remaining(&self) -> usize;
 
-    fn get_slice(&mut self, bytes: usize) -> &[u8];
+    /// Fills `dst` with the next bytes and advances the reader index past them.
+    ///
+    /// #Panic
+    /// May panic when `dst` is longer than `remaining()`.
+    fn copy_to_slice(&mut self, dst: &mut [u8]);
 
     #[inline]
     fn get_slice_bound<const LEN: usize>(&mut self) -> [u8; LEN] {
-        let src = self.get_slice(LEN);
         let mut ret = [0; LEN];
-        for i in 0..LEN {
-            ret[i] = unsafe { *src.get_unchecked(i) };
-        }
+        self.copy_to_slice(&mut ret);
         ret
     }
 
     fn get_u8(&mut self) -> u8;
 
+    #[inline]
+    fn get_i8(&mut self) -> i8 {
+        self.get_u8() as i8
+    }
+
     #[inline]
     fn get_u16_le(&mut self) -> u16 {
         let bytes = self.get_slice_bound::<2>();
@@ -175,28 +196,136 @@ pub trait ReadableBuffer: GenericBuffer + From<&'static [u8]> {
         u128::from_ne_bytes(bytes)
     }
 
-}
+    #[inline]
+    fn get_i16_le(&mut self) -> i16 {
+        let bytes = self.get_slice_bound::<2>();
+        i16::from_le_bytes(bytes)
+    }
 
-pub trait WritableBuffer: GenericBuffer {
+    #[inline]
+    fn get_i16_be(&mut self) -> i16 {
+        let bytes = self.get_slice_bound::<2>();
+        i16::from_be_bytes(bytes)
+    }
 
-    /// Allocates a buffer with at least `capacity` bytes of capacity
-    /// to store information.
-    fn with_capacity(capacity: usize) -> Self;
+    #[inline]
+    fn get_i16_ne(&mut self) -> i16 {
+        let bytes = self.get_slice_bound::<2>();
+        i16::from_ne_bytes(bytes)
+    }
 
-    /// Allocates a zero initialized buffer with at least `len` bytes capacity
-    /// to store information.
-    fn zeroed(len: usize) -> Self;
+    #[inline]
+    fn get_i32_le(&mut self) -> i32 {
+        let bytes = self.get_slice_bound::<4>();
+        i32::from_le_bytes(bytes)
+    }
 
-    /// Resets the writer index to 0, so the buffer
-    /// can be written again from the start.
-    /// Note that this also resets the reader index.
-    fn reset_writer_index(&mut self);
+    #[inline]
+    fn get_i32_be(&mut self) -> i32 {
+        let bytes = self.get_slice_bound::<4>();
+        i32::from_be_bytes(bytes)
+    }
 
-    fn reserve(&mut self, size: usize);
+    #[inline]
+    fn get_i32_ne(&mut self) -> i32 {
+        let bytes = self.get_slice_bound::<4>();
+        i32::from_ne_bytes(bytes)
+    }
 
-    fn resize(&mut self, size: usize);
+    #[inline]
+    fn get_i64_le(&mut self) -> i64 {
+        let bytes = self.get_slice_bound::<8>();
+        i64::from_le_bytes(bytes)
+    }
 
-    fn capacity(&self) -> usize;
+    #[inline]
+    fn get_i64_be(&mut self) -> i64 {
+        let bytes = self.get_slice_bound::<8>();
+        i64::from_be_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_i64_ne(&mut self) -> i64 {
+        let bytes = self.get_slice_bound::<8>();
+        i64::from_ne_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_i128_le(&mut self) -> i128 {
+        let bytes = self.get_slice_bound::<16>();
+        i128::from_le_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_i128_be(&mut self) -> i128 {
+        let bytes = self.get_slice_bound::<16>();
+        i128::from_be_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_i128_ne(&mut self) -> i128 {
+        let bytes = self.get_slice_bound::<16>();
+        i128::from_ne_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_f32_le(&mut self) -> f32 {
+        let bytes = self.get_slice_bound::<4>();
+        f32::from_le_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_f32_be(&mut self) -> f32 {
+        let bytes = self.get_slice_bound::<4>();
+        f32::from_be_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_f32_ne(&mut self) -> f32 {
+        let bytes = self.get_slice_bound::<4>();
+        f32::from_ne_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_f64_le(&mut self) -> f64 {
+        let bytes = self.get_slice_bound::<8>();
+        f64::from_le_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_f64_be(&mut self) -> f64 {
+        let bytes = self.get_slice_bound::<8>();
+        f64::from_be_bytes(bytes)
+    }
+
+    #[inline]
+    fn get_f64_ne(&mut self) -> f64 {
+        let bytes = self.get_slice_bound::<8>();
+        f64::from_ne_bytes(bytes)
+    }
+
+}
+
+/// The reading operations of buffers, which keep their data around so it can be
+/// borrowed and read again.
+pub trait GenericReadableBuffer: GenericBuffer + ReadableBuffer + From<&'static [u8]> {
+
+    #[inline]
+    fn from_static(buf: &'static [u8]) -> Self {
+        <Self as From<&'static [u8]>>::from(buf)
+    }
+
+    /// Resets the reader index to 0 so the buffer can be read
+    /// from the beginning again.
+    fn reset_reader_index(&mut self);
+
+    fn get_slice(&mut self, bytes: usize) -> &[u8];
+
+}
+
+/// Writing access to a sequence of bytes, this is implemented by the buffers that
+/// can be written to and by the producing half of a `BufferRing`.
+pub trait WritableBuffer {
 
     fn put_bytes(&mut self, val: u8, repeat: usize);
 
@@ -204,6 +333,11 @@ pub trait WritableBuffer: GenericBuffer {
 
     fn put_u8(&mut self, val: u8);
 
+    #[inline]
+    fn put_i8(&mut self, val: i8) {
+        self.put_u8(val as u8);
+    }
+
     #[inline]
     fn put_u16_le(&mut self, val: u16) {
         let raw = val.to_le_bytes();
@@ -276,9 +410,142 @@ pub trait WritableBuffer: GenericBuffer {
         self.put_slice(&raw);
     }
 
+    #[inline]
+    fn put_i16_le(&mut self, val: i16) {
+        let raw = val.to_le_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i16_be(&mut self, val: i16) {
+        let raw = val.to_be_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i16_ne(&mut self, val: i16) {
+        let raw = val.to_ne_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i32_le(&mut self, val: i32) {
+        let raw = val.to_le_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i32_be(&mut self, val: i32) {
+        let raw = val.to_be_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i32_ne(&mut self, val: i32) {
+        let raw = val.to_ne_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i64_le(&mut self, val: i64) {
+        let raw = val.to_le_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i64_be(&mut self, val: i64) {
+        let raw = val.to_be_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i64_ne(&mut self, val: i64) {
+        let raw = val.to_ne_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i128_le(&mut self, val: i128) {
+        let raw = val.to_le_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i128_be(&mut self, val: i128) {
+        let raw = val.to_be_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_i128_ne(&mut self, val: i128) {
+        let raw = val.to_ne_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_f32_le(&mut self, val: f32) {
+        let raw = val.to_le_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_f32_be(&mut self, val: f32) {
+        let raw = val.to_be_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_f32_ne(&mut self, val: f32) {
+        let raw = val.to_ne_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_f64_le(&mut self, val: f64) {
+        let raw = val.to_le_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_f64_be(&mut self, val: f64) {
+        let raw = val.to_be_bytes();
+        self.put_slice(&raw);
+    }
+
+    #[inline]
+    fn put_f64_ne(&mut self, val: f64) {
+        let raw = val.to_ne_bytes();
+        self.put_slice(&raw);
+    }
+
+}
+
+/// The writing operations of buffers, which manage their own storage.
+pub trait GenericWritableBuffer: GenericBuffer + WritableBuffer {
+
+    /// Allocates a buffer with at least `capacity` bytes of capacity
+    /// to store information.
+    fn with_capacity(capacity: usize) -> Self;
+
+    /// Allocates a zero initialized buffer with at least `len` bytes capacity
+    /// to store information.
+    fn zeroed(len: usize) -> Self;
+
+    /// Resets the writer index, so the buffer can be written again.
+    /// If `RETAIN_INDICES` is set, the writer index is set to the reader index, which keeps
+    /// the data that was read already and leaves the reader index as it is.
+    /// Otherwise both indices are reset to 0.
+    fn reset_writer_index(&mut self);
+
+    fn reserve(&mut self, size: usize);
+
+    fn resize(&mut self, size: usize);
+
+    fn capacity(&self) -> usize;
+
 }
 
-pub trait ReadonlyBuffer: ReadableBuffer {
+pub trait ReadonlyBuffer: GenericReadableBuffer {
 
     /// the range represents a range offset from the current reader
     /// index.
@@ -286,15 +553,18 @@ pub trait ReadonlyBuffer: ReadableBuffer {
 
 }
 
-pub trait RWBuffer: ReadableBuffer + WritableBuffer {}
+pub trait RWBuffer: GenericReadableBuffer + GenericWritableBuffer {}
 
+#[cfg(test)]
 mod tests {
     use std::mem::size_of;
     use crate::buffer_format::BufferFormat;
-    use crate::buffer_mut::BufferMut;
-    use crate::{GenericBuffer, ReadableBuffer, WritableBuffer};
-    use crate::buffer::Buffer;
-    use crate::buffer_rw::BufferRW;
+    use crate::buffer_format::half::FormatHalf;
+    use crate::buffer_mut::{BufferMut, BufferMutGeneric, LocalBufferMut};
+    use crate::{GenericBuffer, GenericReadableBuffer, GenericWritableBuffer, ReadableBuffer, WritableBuffer};
+    use crate::buffer::{Buffer, BufferGeneric, LocalBuffer};
+    use crate::buffer_rw::{BufferRW, BufferRWGeneric};
+    use crate::growth::{Doubling, DoublingThenLinear, FixedIncrement, GrowthPolicy, OneAndHalf};
 
     #[test]
     fn test_buffer_mut() {
@@ -321,12 +591,12 @@ mod tests {
             println!("only!");
         }
 
-        let mut converted = Buffer::from(buffer_2.clone());
+        let converted = Buffer::from(buffer_2.clone());
         assert_eq!(converted.len(), buffer_2.len());
-        assert!(converted.len() > 0);
+        assert!(!converted.is_empty());
         assert!(converted.capacity() > 0);
         let mut cloned = converted.clone();
-        println!("base ptr: {}", cloned.buffer.ptr as usize);
+        println!("base ptr: {}", cloned.as_ptr() as usize);
         assert_eq!(cloned.len(), converted.len());
         assert_eq!(cloned.capacity(), converted.capacity());
 
@@ -340,7 +610,7 @@ mod tests {
         assert_eq!(buffer.len(), 27);
         buffer.put_u64_le(5);
         assert_eq!(buffer.get_u64_le(), 5);
-        let mut rw_buf: BufferRW = buffer.into();
+        let mut rw_buf: BufferRW = buffer;
         assert_eq!(rw_buf.len(), 35);
         rw_buf.put_u64_le(3);
         rw_buf.shrink();
@@ -361,8 +631,740 @@ mod tests {
         let mut buffer = BufferRW::from(buffer);
         buffer.put_u64_le(5);
         assert_eq!(buffer.get_u64_le(), 5);
-        // let mut buf_mut = BufferMut::from(buffer); // FIXME: figure out why this impl isn't there
-        // buf_mut.put_u8(3);
+        let mut buf_mut = BufferMut::from(buffer);
+        buf_mut.put_u8(3);
+    }
+
+    #[test]
+    fn test_config() {
+        use crate::config::{NoInlineBuffer, NoInlineBufferMut};
+        let mut buffer = NoInlineBufferMut::with_capacity(16);
+        assert!(!buffer.is_inlined());
+        buffer.put_u8(1);
+        buffer.put_u8(2);
+        let buffer = NoInlineBuffer::from(buffer);
+        assert!(!buffer.is_inlined());
+    }
+
+    #[test]
+    fn test_growth() {
+        assert_eq!(Doubling::grow(0, 10), 10);
+        assert_eq!(OneAndHalf::grow(64, 65), 96);
+        assert_eq!(FixedIncrement::<16>::grow(32, 50), 64);
+        assert_eq!(DoublingThenLinear::<128>::grow(64, 65), 128);
+        assert_eq!(DoublingThenLinear::<128>::grow(256, 257), 384);
+        // a buffer with headroom may already be large enough for the requested bytes
+        assert_eq!(FixedIncrement::<16>::grow(64, 50), 80);
+        assert_eq!(DoublingThenLinear::<128>::grow(256, 200), 384);
+        let mut buffer = BufferMutGeneric::<FormatHalf, FixedIncrement<0>, 0, false>::new();
+        for i in 0..64 {
+            buffer.put_u8(i);
+        }
+        assert_eq!(buffer.len(), 64);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_stats() {
+        let before = crate::stats::snapshot();
+        let mut buffer = BufferMut::new();
+        for i in 0..64 {
+            buffer.put_u8(i);
+        }
+        let after = crate::stats::snapshot();
+        assert!(after.outlines > before.outlines);
+        assert!(after.allocations > before.allocations);
+        drop(buffer);
+        assert!(crate::stats::snapshot().deallocations > after.deallocations);
+
+        // converting shared or inlined data into a vec has to copy it
+        let before = crate::stats::snapshot();
+        let buffer = Buffer::from(vec![7u8; 64]);
+        let _shared = buffer.clone();
+        let _: Vec<u8> = buffer.into();
+        let _: Vec<u8> = Buffer::from(&[7u8; 8][..]).into();
+        assert!(crate::stats::snapshot().conversion_copies >= before.conversion_copies + 2);
+    }
+
+    #[test]
+    fn test_alloc_cache() {
+        use crate::util::{alloc_uninit_buffer, dealloc};
+        let alloc = unsafe { alloc_uninit_buffer(64) };
+        unsafe { dealloc(alloc, 64); }
+        // the freed allocation is served from the thread's cache
+        let reused = unsafe { alloc_uninit_buffer(64) };
+        assert_eq!(alloc, reused);
+        unsafe { dealloc(reused, 64); }
+
+        // the sizes `OneAndHalf` grows to are cached as well
+        let alloc = unsafe { alloc_uninit_buffer(96) };
+        unsafe { dealloc(alloc, 96); }
+        assert_eq!(unsafe { alloc_uninit_buffer(96) }, alloc);
+
+        // more allocations than a size class caches are tracked
+        let allocs = (0..40).map(|_| unsafe { alloc_uninit_buffer(128) }).collect::<Vec<_>>();
+        for alloc in allocs {
+            unsafe { dealloc(alloc, 128); }
+        }
+        assert_eq!(crate::util::cached_cnt(128), 32);
+
+        // allocations freed on another thread stay out of its cache
+        let addr = alloc as usize;
+        std::thread::spawn(move || {
+            unsafe { dealloc(addr as *mut u8, 96); }
+            assert_eq!(crate::util::cached_cnt(96), 0);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_ring() {
+        let (mut producer, mut consumer) = crate::ring::BufferRing::with_capacity(16);
+        let writer = std::thread::spawn(move || {
+            producer.put_u8(7);
+            for i in 0..1024u32 {
+                while producer.free() < 4 {
+                    std::hint::spin_loop();
+                }
+                producer.put_u32_le(i);
+            }
+        });
+        while consumer.remaining() < 1 {
+            std::hint::spin_loop();
+        }
+        assert_eq!(consumer.get_u8(), 7);
+        // the leading byte misaligns the values, so some of them wrap around
+        for i in 0..1024u32 {
+            while consumer.remaining() < 4 {
+                std::hint::spin_loop();
+            }
+            assert_eq!(consumer.get_u32_le(), i);
+        }
+        writer.join().unwrap();
+
+        // the halves can be used wherever a readable or writable buffer is expected
+        fn write(dst: &mut impl WritableBuffer) {
+            dst.put_i16_be(-2);
+            dst.put_f64_le(1.5);
+            dst.put_bytes(9, 3);
+        }
+        let (mut producer, mut consumer) = crate::ring::BufferRing::with_capacity(16);
+        producer.put_slice(&[0; 10]);
+        consumer.advance(10);
+        write(&mut producer);
+        assert_eq!(consumer.get_i16_be(), -2);
+        assert_eq!(consumer.get_f64_le(), 1.5);
+        assert_eq!(consumer.get_slice_bound::<3>(), [9; 3]);
+        let mut buffer = BufferMut::new();
+        write(&mut buffer);
+        let mut buffer = BufferRW::from(buffer);
+        assert_eq!(buffer.get_i16_be(), -2);
+        assert_eq!(buffer.get_f64_le(), 1.5);
+    }
+
+    #[test]
+    fn test_buffer_array() {
+        use crate::buffer_array::{BufferArray, CapacityError};
+        let mut buffer = BufferArray::<12>::new();
+        buffer.put_u64_le(5);
+        buffer.put_u16_be(3);
+        assert_eq!(buffer.try_put_u32_le(1), Err(CapacityError { required: 14, capacity: 12 }));
+        buffer.try_put_u16_le(9).unwrap();
+        assert_eq!(buffer.capacity(), 12);
+        assert_eq!(buffer.get_u64_le(), 5);
+        let mut front = buffer.split_to(2);
+        assert_eq!(front.get_u16_be(), 3);
+        assert_eq!(buffer.get_u16_le(), 9);
+
+        // only adjacent parts of the same split can be merged again
+        assert!(front.try_unsplit(BufferArray::try_from(&[1u8, 2][..]).unwrap()).is_err());
+        let back = buffer;
+        front.unsplit(back);
+        assert_eq!(front.len(), 12);
+        // the back part was read, so the reader index continues there
+        assert_eq!(front.remaining(), 0);
+        front.reset_reader_index();
+        assert_eq!(front.get_u64_le(), 5);
+
+        let mut front = BufferArray::<8>::try_from(vec![1, 2, 3, 4]).unwrap();
+        let mut back = front.split_off(2);
+        back.advance(1);
+        // the unread data of the front part would hide that the back part was read from
+        let back = front.try_unsplit(back).unwrap_err();
+        front.advance(2);
+        front.unsplit(back);
+        assert_eq!(front.get_u8(), 4);
+        assert!(BufferArray::<2>::try_from(vec![0; 3]).is_err());
+        assert_eq!(BufferArray::<2>::new().try_put_i16_le(-1), Ok(()));
+    }
+
+    #[test]
+    fn test_inline_words() {
+        use crate::config::WideInlineConfig;
+        crate::buffer_types!(WideInlineConfig<8>, WideBuffer, WideBufferMut, WideBufferRW);
+        let mut buffer = WideBufferMut::new();
+        buffer.put_bytes(1, 60);
+        assert!(buffer.is_inlined());
+        assert_eq!(buffer.capacity(), 64);
+        let rw = WideBufferRW::from(buffer.clone());
+        assert_eq!(rw.len(), 60);
+        let frozen = WideBuffer::from(buffer);
+        assert_eq!(frozen.len(), 60);
+    }
+
+    #[test]
+    fn test_packed_inline() {
+        let data = vec![7u8; 28];
+        let buffer = Buffer::from(data.clone());
+        if cfg!(all(target_pointer_width = "64", target_endian = "little")) {
+            assert!(buffer.is_inlined());
+        }
+        // the data doesn't fit into the inline storage of mutable buffers
+        let buffer = BufferMut::from(buffer);
+        assert!(!buffer.is_inlined());
+        let vec: Vec<u8> = buffer.into();
+        assert_eq!(vec, data);
+        let buffer = BufferMut::from(Buffer::from(vec![1, 2, 3]));
+        assert!(buffer.is_inlined());
+        let vec: Vec<u8> = buffer.into();
+        assert_eq!(vec, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_demotion() {
+        let data = (0..4096u32).map(|i| i as u8).collect::<Vec<u8>>();
+        let mut buffer = Buffer::from(data.clone());
+        let mut header = buffer.split_to(5);
+        assert!(!header.is_inlined());
+        header.shrink();
+        assert!(header.is_inlined());
+        assert_eq!(&*header, &data[..5]);
+        let tail = buffer.split_off(8);
+        assert!(!tail.is_inlined());
+        assert_eq!(tail.len(), 4083);
+        assert_eq!(&*tail, &data[13..]);
+        buffer.shrink();
+        assert!(buffer.is_inlined());
+        assert_eq!(&*buffer, &data[5..13]);
+        // small parts of a split can be rejoined
+        let mut buffer = Buffer::from(vec![1; 200]);
+        let tail = buffer.split_off(190);
+        buffer.unsplit(tail);
+        assert_eq!(&*buffer, &[1; 200][..]);
+        let mut tail = Buffer::from(vec![1; 200]);
+        let mut header = tail.split_to(10);
+        header.unsplit(tail);
+        assert_eq!(&*header, &[1; 200][..]);
+        // the remainder of a large split keeps referencing the storage behind an offset
+        let mut rest = Buffer::from(data.clone());
+        let front = rest.split_to(100);
+        assert_eq!(&*front, &data[..100]);
+        assert_eq!(&*rest, &data[100..]);
+        let mut buffer = BufferMut::with_capacity(1024);
+        buffer.put_u64_le(1);
+        buffer.shrink();
+        assert!(buffer.is_inlined());
+        // aligned buffers stay on the heap to keep their alignment
+        let mut buffer = BufferMut::with_capacity_aligned(1024, 64);
+        buffer.put_u64_le(1);
+        buffer.shrink();
+        assert!(!buffer.is_inlined());
+        assert_eq!(buffer.as_ptr() as usize % 64, 0);
+    }
+
+    #[test]
+    fn test_compact() {
+        use crate::ReadonlyBuffer;
+        use crate::buffer_format::packed::FormatPacked;
+        let data = (0..1u32 << 20).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        // leave room for the metadata, so the buffer adopts the vec as a heap allocation
+        let mut vec = Vec::with_capacity(data.len() + 64);
+        vec.extend_from_slice(&data);
+        let buffer = BufferGeneric::<FormatPacked, Doubling, 64, true, true, true, 16>::from(vec);
+        let key = buffer.slice(..100);
+        assert_ne!(key.as_ptr(), buffer.as_ptr());
+        assert_eq!(&*key, &data[..100]);
+        let value = buffer.slice(1000..1200);
+        assert_eq!(&*value, &data[1000..1200]);
+        let mut half = buffer.slice(1 << 19..);
+        assert_eq!(half.as_ptr(), buffer.as_ptr().wrapping_add(1 << 19));
+        half.unshare();
+        assert_ne!(half.as_ptr(), buffer.as_ptr().wrapping_add(1 << 19));
+        assert_eq!(&*half, &data[1 << 19..]);
+        // external storage and buffers with the default config are never compacted
+        let owned = BufferGeneric::<FormatPacked, Doubling, 64, true, true, true, 16>::from_owner(data.clone());
+        assert_eq!(owned.slice(..100).as_ptr(), owned.as_ptr());
+        let buffer = Buffer::from(data.clone());
+        assert_eq!(buffer.slice(..100).as_ptr(), buffer.as_ptr());
+    }
+
+    #[test]
+    fn test_headroom() {
+        let mut buffer = BufferMut::with_headroom(8, 64);
+        buffer.put_slice(&[7; 40]);
+        let payload = buffer.as_ptr();
+        buffer.put_u16_be_front(40);
+        buffer.put_u8_front(1);
+        assert_eq!(buffer.as_ptr(), payload.wrapping_sub(3));
+        assert_eq!(&buffer[..3], &[1, 0, 40]);
+        assert_eq!(buffer.len(), 43);
+        assert_eq!(buffer.headroom(), 5);
+        // this doesn't fit into the headroom, so the data gets moved
+        buffer.prepend_slice(&[0; 16]);
+        assert_eq!(buffer.len(), 59);
+        assert_eq!(&buffer[16..19], &[1, 0, 40]);
+        // the headroom grows geometrically, so the next prepends fit without moving the data
+        let headroom = buffer.headroom();
+        assert!(headroom >= 16);
+        // growing the data keeps the headroom
+        buffer.put_bytes(9, 256);
+        assert_eq!(buffer.headroom(), headroom);
+        let data = buffer.as_ptr();
+        buffer.prepend_slice(&[2; 16]);
+        assert_eq!(buffer.as_ptr(), data.wrapping_sub(16));
+        assert_eq!(&buffer[..16], &[2; 16]);
+        assert_eq!(&buffer[32..35], &[1, 0, 40]);
+        assert_eq!(&buffer[buffer.len() - 256..], &[9; 256][..]);
+    }
+
+    #[test]
+    fn test_reverse_buffer() {
+        use crate::buffer_reverse::ReverseBufferMut;
+        let mut buffer = ReverseBufferMut::new();
+        buffer.put_u32_le(1);
+        buffer.put_slice(b"table");
+        for i in 0..16 {
+            buffer.put_u64_be(i);
+        }
+        assert_eq!(buffer.len(), 4 + 5 + 16 * 8);
+        assert_eq!(&buffer[..8], &15u64.to_be_bytes());
+        let buffer: Buffer = buffer.freeze();
+        // the headroom that's left isn't part of the frozen buffer
+        assert_eq!(buffer.len(), 4 + 5 + 16 * 8);
+        assert_eq!(&buffer[..8], &15u64.to_be_bytes());
+        assert_eq!(&buffer[buffer.len() - 9..], b"table\x01\0\0\0");
+
+        let mut small = ReverseBufferMut::new();
+        small.put_u16_be(0x102);
+        small.put_u8(3);
+        let small: Buffer = small.freeze();
+        assert_eq!(&*small, &[3, 1, 2]);
+
+        // the storage grows geometrically instead of moving the data on every write
+        let mut buffer = ReverseBufferMut::new();
+        let mut moves = 0;
+        for i in 0..1024u64 {
+            let data = buffer.as_ptr();
+            buffer.put_u64_le(i);
+            if buffer.as_ptr() != data.wrapping_sub(8) {
+                moves += 1;
+            }
+        }
+        assert!(moves <= 12, "the data was moved {} times", moves);
+        assert_eq!(&buffer[..8], &1023u64.to_le_bytes());
+        assert_eq!(&buffer[buffer.len() - 8..], &0u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_retain_indices() {
+        let mut retaining = BufferRW::new();
+        let mut resetting = BufferRWGeneric::<FormatHalf, Doubling, 64, true, true, false>::new();
+        for i in 0..2 {
+            retaining.put_u64_le(i);
+            resetting.put_u64_le(i);
+        }
+        assert_eq!(retaining.get_u64_le(), 0);
+        assert_eq!(resetting.get_u64_le(), 0);
+        let mut retained = Buffer::from(retaining.clone());
+        let mut reset = BufferGeneric::<FormatHalf, Doubling, 64, true, true, false>::from(resetting.clone());
+        assert_eq!(retained.get_u64_le(), 1);
+        assert_eq!(reset.get_u64_le(), 0);
+        retaining.reset_writer_index();
+        resetting.reset_writer_index();
+        assert_eq!(retaining.len(), 8);
+        assert_eq!(resetting.len(), 0);
+
+        let data = (0..64u8).collect::<Vec<u8>>();
+        let mut retaining = Buffer::from(data.clone());
+        let mut resetting = BufferGeneric::<FormatHalf, Doubling, 64, true, true, false>::from(data.clone());
+        let ptr = retaining.as_ptr();
+        retaining.advance(4);
+        resetting.advance(4);
+        let back = retaining.split_off(28);
+        assert_eq!(&*back, &data[32..]);
+        retaining.unsplit(back);
+        assert_eq!(&*retaining, &data[4..]);
+        let back = resetting.split_off(28);
+        resetting.unsplit(back);
+        assert_eq!(&*resetting, &data[..]);
+
+        // read-only buffers only keep their storage on clear if they retain their indices
+        retaining.clear();
+        assert!(retaining.is_empty());
+        assert_eq!(retaining.as_ptr(), ptr);
+        resetting.clear();
+        assert!(resetting.is_empty());
+        assert!(resetting.is_inlined());
+    }
+
+    #[test]
+    fn test_conversions_between_buffers() {
+        let mut buffer = BufferMut::new();
+        for i in 0..8 {
+            buffer.put_u64_le(i);
+        }
+        let ptr = buffer.as_ptr();
+        let buffer = BufferRWGeneric::<FormatHalf, OneAndHalf, 128>::from(buffer);
+        let mut buffer = Buffer::from(buffer);
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert_eq!(buffer.get_u64_le(), 0);
+        let buffer = BufferMutGeneric::<FormatHalf, OneAndHalf, 128>::from(buffer);
+        assert_eq!(buffer.as_ptr(), ptr);
+        let mut buffer = BufferRW::from(buffer);
+        assert_eq!(buffer.get_u64_le(), 1);
+        let buffer = BufferMut::from(buffer);
+        let mut buffer = BufferRW::from(BufferGeneric::<FormatHalf, OneAndHalf, 128>::from(buffer));
+        // the view of readable buffers starts at their reader index
+        assert_eq!(buffer.as_ptr(), ptr.wrapping_add(16));
+        assert_eq!(buffer.get_u64_le(), 2);
+    }
+
+    #[test]
+    fn test_aligned() {
+        let mut buffer = BufferMut::with_capacity_aligned(16, 4096);
+        assert_eq!(buffer.as_ptr() as usize % 4096, 0);
+        for i in 0..1024 {
+            buffer.put_u64_le(i);
+        }
+        assert_eq!(buffer.as_ptr() as usize % 4096, 0);
+        assert_eq!(buffer.len(), 1024 * 8);
+        let buffer = Buffer::from(buffer);
+        let mut shared = buffer.clone();
+        // copying the shared data keeps the alignment
+        shared.unshare();
+        assert_eq!(shared.as_ptr() as usize % 4096, 0);
+        assert_eq!(&shared[..], &buffer[..]);
+        drop(buffer);
+        // as does shrinking the only reference
+        shared.unshare();
+        assert_eq!(shared.as_ptr() as usize % 4096, 0);
+        assert_eq!(shared.len(), 1024 * 8);
+    }
+
+    #[test]
+    fn test_local() {
+        let mut buffer = LocalBufferMut::new();
+        buffer.put_u64_le(1);
+        buffer.put_u128_le(2);
+        let mut buffer = LocalBuffer::from(buffer);
+        let mut cloned = buffer.clone();
+        assert_eq!(buffer.get_u64_le(), 1);
+        assert_eq!(cloned.get_u64_le(), 1);
+        assert_eq!(cloned.get_u128_le(), 2);
+
+        // shared storage has to leave the local counter behind when it's sent across threads
+        let mut local = LocalBufferMut::with_capacity(256);
+        local.put_slice(&[3; 200]);
+        let cloned = local.clone();
+        let buffer = Buffer::from(cloned);
+        assert_ne!(buffer.as_ptr(), local.as_ptr());
+        let handle = std::thread::spawn(move || {
+            let cloned = buffer.clone();
+            assert_eq!(&*cloned, &[3; 200]);
+        });
+        local.put_u8(4);
+        handle.join().unwrap();
+        assert_eq!(local.len(), 201);
+
+        // unshared storage is moved over without copying
+        let mut local = LocalBufferMut::with_capacity(256);
+        local.put_slice(&[5; 200]);
+        let ptr = local.as_ptr();
+        let buffer = Buffer::from(local);
+        assert_eq!(buffer.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_weak() {
+        let mut buffer = BufferMut::new();
+        for i in 0..8 {
+            buffer.put_u64_le(i);
+        }
+        let buffer = Buffer::from(buffer);
+        let weak = buffer.downgrade();
+        let mut upgraded = weak.upgrade().unwrap();
+        assert_eq!(upgraded.get_u64_le(), 0);
+        drop(upgraded);
+        drop(buffer);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_try_into_mut() {
+        let mut buffer = BufferMut::new();
+        for i in 0..8 {
+            buffer.put_u64_le(i);
+        }
+        let buffer = Buffer::from(buffer);
+        let cloned = buffer.clone();
+        let shared: Result<BufferMut, _> = buffer.try_into_mut();
+        let Err(buffer) = shared else {
+            panic!("converted a shared buffer");
+        };
+        drop(cloned);
+        let ptr = buffer.as_ptr();
+        let Ok(mut buffer) = buffer.try_into_mut::<FormatHalf, Doubling, 64, true>() else {
+            panic!("failed converting a unique buffer");
+        };
+        assert_eq!(buffer.as_ptr(), ptr);
+        buffer.put_u64_le(8);
+        assert_eq!(buffer.len(), 9 * 8);
+
+        // inlined data can use the whole inline storage after the conversion
+        let small = Buffer::from(vec![1u8, 2, 3]);
+        assert!(small.is_inlined());
+        let Ok(mut small) = small.try_into_mut::<FormatHalf, Doubling, 64, true>() else {
+            panic!("failed converting an inlined buffer");
+        };
+        assert_eq!(small.capacity(), <FormatHalf as BufferFormat<true, false>>::INLINE_SIZE);
+        small.put_bytes(4, small.capacity() - 3);
+        assert!(small.is_inlined());
+        assert_eq!(&small[..4], &[1, 2, 3, 4]);
+
+        // the reader index is only kept if the mutable buffer retains its indices
+        let filled = || {
+            let mut buffer = BufferMut::new();
+            for i in 0..8 {
+                buffer.put_u64_le(i);
+            }
+            buffer
+        };
+        let mut read = Buffer::from(filled());
+        assert_eq!(read.get_u64_le(), 0);
+        let Ok(retaining) = read.try_into_mut::<FormatHalf, Doubling, 64, true>() else {
+            panic!("failed converting a unique buffer");
+        };
+        assert_eq!(Buffer::from(retaining).get_u64_le(), 1);
+        let mut read = Buffer::from(filled());
+        assert_eq!(read.get_u64_le(), 0);
+        let Ok(resetting) = read.try_into_mut::<FormatHalf, Doubling, 64, false>() else {
+            panic!("failed converting a unique buffer");
+        };
+        assert_eq!(Buffer::from(resetting).get_u64_le(), 0);
+
+        let mut read = BufferRW::from(filled());
+        assert_eq!(read.get_u64_le(), 0);
+        let Ok(retaining) = read.try_into_mut::<FormatHalf, Doubling, 64, true>() else {
+            panic!("failed converting a unique buffer");
+        };
+        assert_eq!(Buffer::from(retaining).get_u64_le(), 1);
+        let mut read = BufferRW::from(filled());
+        assert_eq!(read.get_u64_le(), 0);
+        let Ok(resetting) = read.try_into_mut::<FormatHalf, Doubling, 64, false>() else {
+            panic!("failed converting a unique buffer");
+        };
+        assert_eq!(Buffer::from(resetting).get_u64_le(), 0);
+    }
+
+    #[test]
+    fn test_cow() {
+        let mut buffer = BufferMut::new();
+        for i in 0..8 {
+            buffer.put_u64_le(i);
+        }
+        let mut cloned = buffer.clone();
+        assert_eq!(cloned.as_ptr(), buffer.as_ptr());
+        cloned.truncate(8);
+        cloned.put_u64_le(10);
+        assert_ne!(cloned.as_ptr(), buffer.as_ptr());
+        let mut buffer = Buffer::from(buffer);
+        assert_eq!(buffer.get_u64_le(), 0);
+        assert_eq!(buffer.get_u64_le(), 1);
+        let mut cloned = Buffer::from(cloned);
+        assert_eq!(cloned.get_u64_le(), 0);
+        assert_eq!(cloned.get_u64_le(), 10);
+
+        // split parts write to their own part of the allocation and can be rejoined
+        let mut front = BufferMut::with_capacity(256);
+        front.put_slice(&[1; 100]);
+        let ptr = front.as_ptr();
+        let mut back = front.split();
+        back.put_slice(&[2; 10]);
+        assert_eq!(back.as_ptr(), ptr.wrapping_add(100));
+        assert!(front.try_unsplit(back).is_ok());
+        assert_eq!(front.as_ptr(), ptr);
+        assert_eq!(&front[..100], &[1; 100]);
+        assert_eq!(&front[100..], &[2; 10]);
+
+        let mut front = BufferRW::with_capacity(256);
+        front.put_slice(&[1; 100]);
+        let mut back = front.split_off(50);
+        let ptr = back.as_ptr();
+        back.put_slice(&[2; 10]);
+        assert_eq!(back.as_ptr(), ptr);
+        assert!(front.try_unsplit(back).is_ok());
+        assert_eq!(&front[..100], &[1; 100]);
+        assert_eq!(&front[100..], &[2; 10]);
+
+        // clones of split parts still copy on the first write
+        let mut front = BufferMut::with_capacity(256);
+        front.put_slice(&[1; 100]);
+        let mut back = front.split();
+        let cloned = back.clone();
+        back.put_u8(2);
+        assert_ne!(back.as_ptr(), cloned.as_ptr());
+        assert!(cloned.is_empty());
+    }
+
+    #[test]
+    fn test_unique() {
+        let mut buffer = BufferMut::with_capacity(64);
+        buffer.put_u64_le(1);
+        // buffers that weren't shared yet set up their reference counter on clone
+        let cloned = buffer.clone();
+        assert_eq!(cloned.as_ptr(), buffer.as_ptr());
+        assert_eq!(&*cloned, &*buffer);
+        drop(cloned);
+        buffer.put_u64_le(3);
+        assert_eq!(&buffer[8..], &3u64.to_le_bytes());
+        let mut other = buffer.split_off(0);
+        other.put_u64_le(2);
+        let buffer = Buffer::from(buffer);
+        assert_eq!(buffer.clone().as_ptr(), buffer.as_ptr());
+
+        // unique buffers may be cloned from several threads at once
+        let mut buffer = BufferMut::with_capacity(64);
+        buffer.put_u64_le(4);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let cloned = buffer.clone();
+                    assert_eq!(cloned.as_ptr(), buffer.as_ptr());
+                });
+            }
+        });
+
+        // read-write buffers adopt vecs without room for the metadata as they are
+        let vec = vec![5u8; 100];
+        let ptr = vec.as_ptr();
+        let mut buffer = BufferRW::from(vec);
+        assert_eq!(buffer.as_ptr(), ptr);
+        buffer.advance(50);
+        let vec: Vec<u8> = buffer.into();
+        assert_eq!(vec.as_ptr(), ptr);
+        let buffer = BufferRW::from(vec);
+        let cloned = buffer.clone();
+        assert_ne!(cloned.as_ptr(), buffer.as_ptr());
+        assert_eq!(&*cloned, &*buffer);
+        let mut buffer = BufferRW::with_capacity(64);
+        buffer.put_u64_le(6);
+        let cloned = buffer.clone();
+        assert_eq!(cloned.as_ptr(), buffer.as_ptr());
+        assert_eq!(Buffer::from(cloned).get_u64_le(), 6);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_map_file() {
+        let path = std::env::temp_dir().join("buffer_test_map_file");
+        let data = (0..64u64).flat_map(|i| i.to_le_bytes()).collect::<Vec<u8>>();
+        std::fs::write(&path, &data).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        let mut buffer: Buffer = unsafe { Buffer::map_file_range(&file, 8, 16) }.unwrap();
+        let mut cloned = buffer.clone();
+        assert_eq!(buffer.get_u64_le(), 1);
+        assert_eq!(buffer.get_u64_le(), 2);
+        drop(buffer);
+        assert_eq!(cloned.get_u64_le(), 1);
+        drop(cloned);
+        // the mapping has to start at a page boundary, so the data is located behind an offset
+        let buffer: Buffer = unsafe { Buffer::map_file_range(&file, 13, 300) }.unwrap();
+        assert_eq!(&*buffer, &data[13..313]);
+        let err = unsafe { Buffer::map_file_range(&file, 13, data.len()) }.err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_from_owner() {
+        let data: std::sync::Arc<[u8]> = (0..8u64).flat_map(|i| i.to_le_bytes()).collect();
+        let mut buffer = Buffer::from_owner(data.clone());
+        assert_eq!(std::sync::Arc::strong_count(&data), 2);
+        assert_eq!(buffer.as_ptr(), data.as_ptr());
+        assert_eq!(buffer.get_u64_le(), 0);
+        assert_eq!(buffer.get_u64_le(), 1);
+        drop(buffer);
+        assert_eq!(std::sync::Arc::strong_count(&data), 1);
+
+        // owners that can't hand out a vec get copied and released
+        let vec: Vec<u8> = Buffer::from_owner(data.clone()).into();
+        assert_eq!(vec, &data[..]);
+        assert_ne!(vec.as_ptr(), data.as_ptr());
+        assert_eq!(std::sync::Arc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let text = "a string that is too long to be stored inline";
+        let buffer = Buffer::from(String::from(text));
+        assert_eq!(buffer.try_into_string().unwrap(), text);
+        let buffer = Buffer::from(text);
+        assert_eq!(buffer.as_ptr(), text.as_ptr());
+        let boxed: Box<[u8]> = text.as_bytes().into();
+        let ptr = boxed.as_ptr();
+        let buffer = Buffer::from(boxed);
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert_eq!(&*buffer.into_boxed_slice(), text.as_bytes());
+        let mut buffer = BufferMut::from(std::borrow::Cow::Borrowed(text.as_bytes()));
+        buffer.put_u8(b'!');
+        assert!(Buffer::from(buffer).try_into_string().unwrap().ends_with('!'));
+
+        // only the unread data is converted
+        let mut buffer = Buffer::from(String::from(text));
+        buffer.advance(2);
+        assert_eq!(buffer.clone().try_into_string().unwrap(), &text[2..]);
+        assert_eq!(&*buffer.into_boxed_slice(), &text.as_bytes()[2..]);
+
+        // boxed slices have no spare capacity for the metadata
+        let boxed: Box<[u8]> = text.as_bytes().into();
+        let mut buffer = BufferMut::from(boxed.clone());
+        buffer.put_u8(b'!');
+        assert_eq!(&buffer[..text.len()], text.as_bytes());
+        let mut buffer = BufferRW::from(boxed);
+        buffer.put_u8(b'!');
+        assert_eq!(buffer.get_slice(text.len()), text.as_bytes());
+        assert_eq!(buffer.get_u8(), b'!');
+    }
+
+    #[test]
+    fn test_from_full_vec() {
+        let vec = vec![7u8; 64];
+        assert_eq!(vec.len(), vec.capacity());
+        let ptr = vec.as_ptr();
+        let buffer = Buffer::from(vec);
+        assert_eq!(buffer.as_ptr(), ptr);
+        let other = buffer.clone();
+        drop(buffer);
+        let vec: Vec<u8> = other.into();
+        assert_eq!(vec.as_ptr(), ptr);
+        assert_eq!(vec, [7u8; 64]);
+
+        // mutable buffers adopt the vec as it is until they get shared or grow
+        let data = (0..64u8).collect::<Vec<u8>>();
+        let vec = data.clone();
+        let ptr = vec.as_ptr();
+        let buffer = BufferMut::from(vec);
+        assert_eq!(buffer.as_ptr(), ptr);
+        let vec: Vec<u8> = buffer.into();
+        assert_eq!(vec.as_ptr(), ptr);
+        let mut buffer = BufferMut::from(vec);
+        let cloned = buffer.clone();
+        assert_eq!(&*cloned, &data[..]);
+        buffer.share();
+        assert_eq!(&*buffer, &data[..]);
+        assert_eq!(buffer.clone().as_ptr(), buffer.as_ptr());
+        buffer.put_u8(64);
+        assert_eq!(&buffer[..64], &data[..]);
+        assert_eq!(buffer[64], 64);
     }
 
     #[test]
@@ -373,8 +1375,8 @@ mod tests {
         let original_len = buffer.len();
         let mut buffer = Buffer::from(buffer);
         let mut other = buffer.split_off(9);
-        println!("other: len {} rdx {}", other.len(), other.0.rdx());
-        println!("buffer: len {} rdx {}", buffer.len(), buffer.0.rdx());
+        println!("other: len {} rdx {}", other.len(), BufferFormat::<true>::rdx(&other.0));
+        println!("buffer: len {} rdx {}", buffer.len(), BufferFormat::<true>::rdx(&buffer.0));
         assert_eq!(other.remaining() + buffer.remaining(), original_len);
         other.unsplit(buffer);
         assert_eq!(other.remaining(), original_len);
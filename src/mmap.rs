@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::external::ExternalHeader;
+use crate::ref_cnt::RefCnt;
+use crate::storage::ExternalStorage;
+
+/// Returns the page size of the system, it's only queried once.
+pub(crate) fn page_size() -> io::Result<usize> {
+    static PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+    let cached = PAGE_SIZE.load(Ordering::Relaxed);
+    if cached != 0 {
+        return Ok(cached);
+    }
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    // sysconf returns -1 on failure
+    if page_size <= 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let page_size = page_size as usize;
+    PAGE_SIZE.store(page_size, Ordering::Relaxed);
+    Ok(page_size)
+}
+
+/// Maps `len` bytes of `file` starting at `offset` into memory and returns
+/// the header of the mapping alongside the offset of the requested data
+/// inside the mapping, as mappings have to start at a page boundary.
+///
+/// SAFETY: the mapped part of the file may not be modified while the mapping is alive.
+pub(crate) unsafe fn map<RC: RefCnt>(file: &File, offset: u64, len: usize) -> io::Result<(*mut ExternalHeader, usize)> {
+    let page_size = page_size()? as u64;
+    let page_offset = (offset % page_size) as usize;
+    let map_len = len + page_offset;
+    // `off_t` is only 32 bits wide on some 32-bit targets
+    let map_offset = libc::off_t::try_from(offset - page_offset as u64)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the offset can't be mapped on this target"))?;
+    let ptr = unsafe { libc::mmap(std::ptr::null_mut(), map_len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), map_offset) };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let header = Box::into_raw(Box::new(ExternalHeader::new(ptr.cast::<u8>(), map_len, release_mapping, ExternalStorage::<RC>::VTABLE)));
+    Ok((header, page_offset))
+}
+
+unsafe fn release_mapping(header: *mut ExternalHeader) {
+    let header = unsafe { Box::from_raw(header) };
+    unsafe { libc::munmap(header.data.cast::<libc::c_void>(), header.len); }
+}
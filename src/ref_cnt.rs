@@ -0,0 +1,110 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The strategy used to update the reference counter stored in a buffer's metadata.
+///
+/// Buffers whose reference counter isn't `Send` and `Sync` will also not
+/// be `Send` and `Sync` themselves.
+pub trait RefCnt: Sized + Copy + Clone {
+
+    /// whether the counter may be updated from multiple threads, shared storage can
+    /// only be handed over between buffers whose counters agree on this.
+    const ATOMIC: bool;
+
+    fn load(ref_cnt: &AtomicUsize) -> usize;
+
+    /// increments the ref cnt by one, returning the previous value.
+    fn increment(ref_cnt: &AtomicUsize) -> usize;
+
+    /// decrements the ref cnt by one, returning the previous value.
+    fn decrement(ref_cnt: &AtomicUsize) -> usize;
+
+    /// increments the ref cnt by one unless it already dropped to 0,
+    /// returns whether the ref cnt was incremented.
+    fn try_increment(ref_cnt: &AtomicUsize) -> bool;
+
+}
+
+/// A reference counter which can be shared across threads.
+#[derive(Clone, Copy, Debug)]
+pub struct AtomicRefCnt;
+
+impl RefCnt for AtomicRefCnt {
+    const ATOMIC: bool = true;
+
+    #[inline]
+    fn load(ref_cnt: &AtomicUsize) -> usize {
+        ref_cnt.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn increment(ref_cnt: &AtomicUsize) -> usize {
+        ref_cnt.fetch_add(1, Ordering::AcqRel) // FIXME: can we choose a weaker ordering?
+    }
+
+    #[inline]
+    fn decrement(ref_cnt: &AtomicUsize) -> usize {
+        ref_cnt.fetch_sub(1, Ordering::AcqRel) // FIXME: can we choose a weaker ordering?
+    }
+
+    #[inline]
+    fn try_increment(ref_cnt: &AtomicUsize) -> bool {
+        ref_cnt.fetch_update(Ordering::AcqRel, Ordering::Acquire, |val| if val == 0 {
+            None
+        } else {
+            Some(val + 1)
+        }).is_ok()
+    }
+}
+
+/// A reference counter that uses plain loads and stores, buffers using it
+/// can't be sent or shared across threads.
+#[derive(Clone, Copy, Debug)]
+pub struct LocalRefCnt(PhantomData<*mut ()>);
+
+impl LocalRefCnt {
+    #[inline]
+    fn cell(ref_cnt: &AtomicUsize) -> &Cell<usize> {
+        // SAFETY: `AtomicUsize` has the same in-memory representation as `usize` and both
+        // types permit mutation through shared references. All buffers referencing this
+        // counter live on the current thread as buffers using it are neither `Send` nor `Sync`.
+        unsafe { &*(ref_cnt as *const AtomicUsize).cast::<Cell<usize>>() }
+    }
+}
+
+impl RefCnt for LocalRefCnt {
+    const ATOMIC: bool = false;
+
+    #[inline]
+    fn load(ref_cnt: &AtomicUsize) -> usize {
+        Self::cell(ref_cnt).get()
+    }
+
+    #[inline]
+    fn increment(ref_cnt: &AtomicUsize) -> usize {
+        let cell = Self::cell(ref_cnt);
+        let prev = cell.get();
+        cell.set(prev + 1);
+        prev
+    }
+
+    #[inline]
+    fn decrement(ref_cnt: &AtomicUsize) -> usize {
+        let cell = Self::cell(ref_cnt);
+        let prev = cell.get();
+        cell.set(prev - 1);
+        prev
+    }
+
+    #[inline]
+    fn try_increment(ref_cnt: &AtomicUsize) -> bool {
+        let cell = Self::cell(ref_cnt);
+        let prev = cell.get();
+        if prev == 0 {
+            return false;
+        }
+        cell.set(prev + 1);
+        true
+    }
+}
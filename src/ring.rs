@@ -0,0 +1,211 @@
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::util::{alloc_zeroed_buffer, dealloc};
+use crate::{ReadableBuffer, WritableBuffer};
+
+/// A fixed capacity ring of bytes shared between a single producer and a single consumer,
+/// which may live on different threads.
+///
+/// The producer implements `WritableBuffer` and the consumer implements `ReadableBuffer`,
+/// their `put_*` and `get_*` methods handle data that wraps around the end of the ring.
+pub struct BufferRing {
+    ptr: *mut u8,
+    /// this is always a power of two, so positions can be masked instead of wrapped.
+    cap: usize,
+    /// the total number of bytes written, this is only updated by the producer.
+    head: AtomicUsize,
+    /// the total number of bytes read, this is only updated by the consumer.
+    tail: AtomicUsize,
+}
+
+unsafe impl Send for BufferRing {}
+unsafe impl Sync for BufferRing {}
+
+impl BufferRing {
+
+    /// Creates a ring which can hold at least `capacity` bytes, the capacity
+    /// is rounded up to the next power of two.
+    pub fn with_capacity(capacity: usize) -> (RingProducer, RingConsumer) {
+        let cap = capacity.max(1).next_power_of_two();
+        // the memory is zeroed, so the unwritten space can be handed out as initialized bytes
+        let ring = Arc::new(Self {
+            ptr: alloc_zeroed_buffer(cap),
+            cap,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        });
+        (RingProducer { ring: ring.clone() }, RingConsumer { ring })
+    }
+
+    #[inline]
+    fn pos(&self, idx: usize) -> usize {
+        idx & (self.cap - 1)
+    }
+
+}
+
+impl Drop for BufferRing {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.cap); }
+    }
+}
+
+/// The writing half of a `BufferRing`.
+pub struct RingProducer {
+    ring: Arc<BufferRing>,
+}
+
+impl RingProducer {
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.cap
+    }
+
+    /// returns the number of bytes that can be written before the ring is full.
+    #[inline]
+    pub fn free(&self) -> usize {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        self.ring.cap - head.wrapping_sub(tail)
+    }
+
+    /// returns the contiguous free space in front of the writer, this may be smaller than
+    /// `free()` if the free space wraps around. Written bytes get visible to the consumer
+    /// through `commit`.
+    #[inline]
+    pub fn chunk_mut(&mut self) -> &mut [u8] {
+        let free = self.free();
+        let pos = self.ring.pos(self.ring.head.load(Ordering::Relaxed));
+        let len = free.min(self.ring.cap - pos);
+        // SAFETY: the consumer never reads the free space
+        unsafe { &mut *ptr::slice_from_raw_parts_mut(self.ring.ptr.add(pos), len) }
+    }
+
+    /// Makes the next `amount` bytes written through `chunk_mut` visible to the consumer.
+    ///
+    /// #Panic
+    /// Panics when amount exceeds `free()`.
+    #[inline]
+    pub fn commit(&mut self, amount: usize) {
+        let free = self.free();
+        if amount > free {
+            panic!("not enough space in ring, tried to commit {} bytes but only {} bytes are free", amount, free);
+        }
+        let head = self.ring.head.load(Ordering::Relaxed);
+        self.ring.head.store(head.wrapping_add(amount), Ordering::Release);
+    }
+
+}
+
+impl WritableBuffer for RingProducer {
+
+    /// #Panic
+    /// Panics when `repeat` bytes don't fit into the free space.
+    fn put_bytes(&mut self, val: u8, repeat: usize) {
+        let free = self.free();
+        if repeat > free {
+            panic!("not enough space in ring, tried to write {} bytes but only {} bytes are free", repeat, free);
+        }
+        let pos = self.ring.pos(self.ring.head.load(Ordering::Relaxed));
+        let first = repeat.min(self.ring.cap - pos);
+        unsafe { ptr::write_bytes(self.ring.ptr.add(pos), val, first); }
+        unsafe { ptr::write_bytes(self.ring.ptr, val, repeat - first); }
+        self.commit(repeat);
+    }
+
+    /// #Panic
+    /// Panics when `val` doesn't fit into the free space.
+    fn put_slice(&mut self, val: &[u8]) {
+        let free = self.free();
+        if val.len() > free {
+            panic!("not enough space in ring, tried to write {} bytes but only {} bytes are free", val.len(), free);
+        }
+        let pos = self.ring.pos(self.ring.head.load(Ordering::Relaxed));
+        // the free space may wrap around the end of the ring
+        let first = val.len().min(self.ring.cap - pos);
+        unsafe { ptr::copy_nonoverlapping(val.as_ptr(), self.ring.ptr.add(pos), first); }
+        unsafe { ptr::copy_nonoverlapping(val.as_ptr().add(first), self.ring.ptr, val.len() - first); }
+        self.commit(val.len());
+    }
+
+    #[inline]
+    fn put_u8(&mut self, val: u8) {
+        self.put_slice(&[val]);
+    }
+
+}
+
+/// The reading half of a `BufferRing`.
+pub struct RingConsumer {
+    ring: Arc<BufferRing>,
+}
+
+impl RingConsumer {
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.cap
+    }
+
+    /// returns the contiguous readable bytes in front of the reader, this may be
+    /// shorter than `remaining()` if the data wraps around.
+    #[inline]
+    pub fn chunk(&self) -> &[u8] {
+        let remaining = self.remaining();
+        let pos = self.ring.pos(self.ring.tail.load(Ordering::Relaxed));
+        let len = remaining.min(self.ring.cap - pos);
+        // SAFETY: the producer never writes to data that wasn't read yet
+        unsafe { &*ptr::slice_from_raw_parts(self.ring.ptr.add(pos), len) }
+    }
+
+}
+
+impl ReadableBuffer for RingConsumer {
+
+    /// Advances the reader index by `amount`, which frees the space for the producer.
+    ///
+    /// #Panic
+    /// Panics when amount exceeds `remaining()`.
+    #[inline]
+    fn advance(&mut self, amount: usize) {
+        let remaining = self.remaining();
+        if amount > remaining {
+            panic!("not enough bytes in ring, expected {} readable bytes but only {} bytes are left", amount, remaining);
+        }
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        self.ring.tail.store(tail.wrapping_add(amount), Ordering::Release);
+    }
+
+    /// this will return the amount of remaining bytes that can be
+    /// read from this ring
+    #[inline]
+    fn remaining(&self) -> usize {
+        let head = self.ring.head.load(Ordering::Acquire);
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        head.wrapping_sub(tail)
+    }
+
+    /// Fills `dst` with the next bytes, even if they wrap around the end of the ring.
+    ///
+    /// #Panic
+    /// Panics when `dst` is longer than `remaining()`.
+    fn copy_to_slice(&mut self, dst: &mut [u8]) {
+        let remaining = self.remaining();
+        if dst.len() > remaining {
+            panic!("not enough bytes in ring, expected {} readable bytes but only {} bytes are left", dst.len(), remaining);
+        }
+        let pos = self.ring.pos(self.ring.tail.load(Ordering::Relaxed));
+        let first = dst.len().min(self.ring.cap - pos);
+        unsafe { ptr::copy_nonoverlapping(self.ring.ptr.add(pos), dst.as_mut_ptr(), first); }
+        unsafe { ptr::copy_nonoverlapping(self.ring.ptr, dst.as_mut_ptr().add(first), dst.len() - first); }
+        self.advance(dst.len());
+    }
+
+    #[inline]
+    fn get_u8(&mut self) -> u8 {
+        self.get_slice_bound::<1>()[0]
+    }
+
+}
@@ -0,0 +1,133 @@
+//! Process wide counters of allocations and other events buffers go through,
+//! these are only updated if the `stats` feature is enabled.
+
+#[cfg(feature = "stats")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "stats")]
+struct Counters {
+    allocations: AtomicUsize,
+    deallocations: AtomicUsize,
+    live_bytes: AtomicUsize,
+    reallocations: AtomicUsize,
+    outlines: AtomicUsize,
+    conversion_copies: AtomicUsize,
+    static_promotions: AtomicUsize,
+}
+
+#[cfg(feature = "stats")]
+static COUNTERS: Counters = Counters {
+    allocations: AtomicUsize::new(0),
+    deallocations: AtomicUsize::new(0),
+    live_bytes: AtomicUsize::new(0),
+    reallocations: AtomicUsize::new(0),
+    outlines: AtomicUsize::new(0),
+    conversion_copies: AtomicUsize::new(0),
+    static_promotions: AtomicUsize::new(0),
+};
+
+/// A snapshot of the counters, the counters are updated independently
+/// of each other, so they may be slightly out of sync.
+#[cfg(feature = "stats")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// the number of allocations made for buffers.
+    pub allocations: usize,
+    /// the number of allocations of buffers that were freed.
+    pub deallocations: usize,
+    /// the number of bytes that are currently allocated for buffers, this includes
+    /// allocations adopted from and excludes allocations handed over to `Vec`s.
+    pub live_bytes: usize,
+    /// the number of times a buffer's allocation was too small and got replaced.
+    pub reallocations: usize,
+    /// the number of times an inlined buffer outgrew the inline space.
+    pub outlines: usize,
+    /// the number of conversions between buffer types or into `Vec`s that had to copy the data.
+    pub conversion_copies: usize,
+    /// the number of static buffers whose data was copied to the heap.
+    pub static_promotions: usize,
+}
+
+/// Returns the current values of all counters.
+#[cfg(feature = "stats")]
+pub fn snapshot() -> Stats {
+    Stats {
+        allocations: COUNTERS.allocations.load(Ordering::Relaxed),
+        deallocations: COUNTERS.deallocations.load(Ordering::Relaxed),
+        live_bytes: COUNTERS.live_bytes.load(Ordering::Relaxed),
+        reallocations: COUNTERS.reallocations.load(Ordering::Relaxed),
+        outlines: COUNTERS.outlines.load(Ordering::Relaxed),
+        conversion_copies: COUNTERS.conversion_copies.load(Ordering::Relaxed),
+        static_promotions: COUNTERS.static_promotions.load(Ordering::Relaxed),
+    }
+}
+
+/// Reports the current values of all counters to the `metrics` recorder,
+/// the live bytes are reported as a gauge and everything else as counters.
+#[cfg(feature = "metrics")]
+pub fn report() {
+    let stats = snapshot();
+    metrics::counter!("buffer.allocations").absolute(stats.allocations as u64);
+    metrics::counter!("buffer.deallocations").absolute(stats.deallocations as u64);
+    metrics::gauge!("buffer.live_bytes").set(stats.live_bytes as f64);
+    metrics::counter!("buffer.reallocations").absolute(stats.reallocations as u64);
+    metrics::counter!("buffer.outlines").absolute(stats.outlines as u64);
+    metrics::counter!("buffer.conversion_copies").absolute(stats.conversion_copies as u64);
+    metrics::counter!("buffer.static_promotions").absolute(stats.static_promotions as u64);
+}
+
+#[inline(always)]
+pub(crate) fn on_alloc(_bytes: usize) {
+    #[cfg(feature = "stats")]
+    {
+        COUNTERS.allocations.fetch_add(1, Ordering::Relaxed);
+        COUNTERS.live_bytes.fetch_add(_bytes, Ordering::Relaxed);
+    }
+}
+
+#[inline(always)]
+pub(crate) fn on_dealloc(_bytes: usize) {
+    #[cfg(feature = "stats")]
+    {
+        COUNTERS.deallocations.fetch_add(1, Ordering::Relaxed);
+        COUNTERS.live_bytes.fetch_sub(_bytes, Ordering::Relaxed);
+    }
+}
+
+/// an allocation owned by a `Vec` is now owned by a buffer.
+#[inline(always)]
+pub(crate) fn on_adopt(_bytes: usize) {
+    #[cfg(feature = "stats")]
+    COUNTERS.live_bytes.fetch_add(_bytes, Ordering::Relaxed);
+}
+
+/// an allocation owned by a buffer is now owned by a `Vec`.
+#[inline(always)]
+pub(crate) fn on_hand_over(_bytes: usize) {
+    #[cfg(feature = "stats")]
+    COUNTERS.live_bytes.fetch_sub(_bytes, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub(crate) fn on_realloc() {
+    #[cfg(feature = "stats")]
+    COUNTERS.reallocations.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub(crate) fn on_outline() {
+    #[cfg(feature = "stats")]
+    COUNTERS.outlines.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub(crate) fn on_conversion_copy() {
+    #[cfg(feature = "stats")]
+    COUNTERS.conversion_copies.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub(crate) fn on_static_promotion() {
+    #[cfg(feature = "stats")]
+    COUNTERS.static_promotions.fetch_add(1, Ordering::Relaxed);
+}
@@ -0,0 +1,214 @@
+use std::marker::PhantomData;
+use std::process::abort;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::external::{take_vec, ExternalHeader};
+use crate::ref_cnt::RefCnt;
+use crate::stats;
+use crate::util::{claim_unique, dealloc_counted, has_weak, meta_align, meta_of, Metadata};
+
+/// The operations that differ between the kinds of storage an outlined buffer can reference.
+/// New kinds of storage only have to provide one of these in order to be usable by all buffers.
+///
+/// All functions get passed the reference ptr and capacity of the buffer.
+/// The vtable of external storage is stored in its header, see `ExternalHeader`.
+pub(crate) struct StorageVTable {
+    /// acquires an additional reference to the storage for a buffer viewing the same bytes.
+    pub(crate) clone: unsafe fn(ptr: *mut u8, cap: usize),
+    /// acquires an additional reference to the storage for a buffer viewing a part of it
+    /// no other buffer views, as the parts created by `split*` do.
+    pub(crate) split: unsafe fn(ptr: *mut u8, cap: usize),
+    /// releases a reference to the storage and frees it if it was the last one.
+    pub(crate) drop: unsafe fn(ptr: *mut u8, cap: usize),
+    /// converts a reference to the storage into a vec containing `len` bytes starting at `offset`.
+    /// this consumes the reference and reuses the storage if possible.
+    pub(crate) to_vec: unsafe fn(ptr: *mut u8, cap: usize, offset: usize, len: usize) -> Vec<u8>,
+    /// returns whether the caller holds the only reference to the storage and may mutate it in place.
+    /// this has to return false for external storage as its data isn't stored at the reference ptr.
+    pub(crate) is_unique: unsafe fn(ptr: *mut u8, cap: usize) -> bool,
+    /// returns whether the caller may write to the bytes it views in place, which is the case if
+    /// no other buffer views them, even if the storage itself is shared.
+    pub(crate) is_writable: unsafe fn(ptr: *mut u8, cap: usize) -> bool,
+}
+
+const MAX_REF_CNT: usize = usize::MAX / 2;
+
+#[inline]
+fn increment_ref_cnt<RC: RefCnt>(ref_cnt: &AtomicUsize) {
+    let val = RC::increment(ref_cnt);
+    if val > MAX_REF_CNT {
+        abort();
+    }
+}
+
+/// releases a reference of the storage the metadata belongs to, returns whether
+/// the storage has to be freed.
+///
+/// SAFETY: `meta` has to point to the metadata of a live storage.
+#[inline]
+unsafe fn release_ref<RC: RefCnt>(meta: *mut Metadata) -> bool {
+    let ref_cnt = unsafe { &(*meta).ref_cnt };
+    // fast path for single ref cnt scenarios, this can't be used if weak buffers
+    // exist as they might upgrade concurrently
+    if RC::load(ref_cnt) == 1 && !unsafe { has_weak(meta) } {
+        return true;
+    }
+    RC::decrement(ref_cnt) == 1
+}
+
+/// copies the data of storage that can't be handed out as a vec.
+#[inline]
+unsafe fn copy_to_vec(ptr: *const u8, len: usize) -> Vec<u8> {
+    stats::on_conversion_copy();
+    unsafe { std::slice::from_raw_parts(ptr, len) }.to_vec()
+}
+
+/// Storage which is allocated on the heap and has its metadata stored at its end.
+pub(crate) struct HeapStorage<RC: RefCnt>(PhantomData<RC>);
+
+impl<RC: RefCnt> HeapStorage<RC> {
+
+    pub(crate) const VTABLE: &'static StorageVTable = &StorageVTable {
+        clone: Self::clone,
+        split: Self::split,
+        drop: Self::drop,
+        to_vec: Self::to_vec,
+        is_unique: Self::is_unique,
+        is_writable: Self::is_writable,
+    };
+
+    unsafe fn clone(ptr: *mut u8, cap: usize) {
+        let meta = unsafe { &*meta_of(ptr, cap) };
+        meta.mark_overlapping();
+        increment_ref_cnt::<RC>(&meta.ref_cnt);
+    }
+
+    unsafe fn split(ptr: *mut u8, cap: usize) {
+        increment_ref_cnt::<RC>(unsafe { &(*meta_of(ptr, cap)).ref_cnt });
+    }
+
+    unsafe fn drop(ptr: *mut u8, cap: usize) {
+        if unsafe { release_ref::<RC>(meta_of(ptr, cap)) } {
+            unsafe { dealloc_counted(ptr, cap); }
+        }
+    }
+
+    unsafe fn to_vec(ptr: *mut u8, cap: usize, offset: usize, len: usize) -> Vec<u8> {
+        // try reusing the allocation, this is only possible if it has the alignment `Vec` expects
+        if offset == 0 && unsafe { meta_align(ptr, cap) } == 1 && unsafe { Self::is_unique(ptr, cap) } {
+            stats::on_hand_over(cap);
+            return unsafe { Vec::from_raw_parts(ptr, len, cap) };
+        }
+        let ret = unsafe { copy_to_vec(ptr.add(offset), len) };
+        unsafe { Self::drop(ptr, cap); }
+        ret
+    }
+
+    unsafe fn is_unique(ptr: *mut u8, cap: usize) -> bool {
+        unsafe { claim_unique::<RC>(meta_of(ptr, cap)) }
+    }
+
+    unsafe fn is_writable(ptr: *mut u8, cap: usize) -> bool {
+        let meta = unsafe { meta_of(ptr, cap) };
+        if unsafe { claim_unique::<RC>(meta) } {
+            // nobody else views the storage anymore
+            unsafe { &*meta }.overlapping.store(false, Ordering::Relaxed);
+            return true;
+        }
+        !unsafe { &*meta }.overlapping.load(Ordering::Acquire)
+    }
+
+}
+
+/// Storage which lives for the whole program and thus doesn't need to be reference counted.
+/// This is also used for the empty sentinel.
+pub(crate) struct StaticStorage;
+
+impl StaticStorage {
+
+    pub(crate) const VTABLE: &'static StorageVTable = &StorageVTable {
+        clone: Self::clone,
+        split: Self::clone,
+        drop: Self::drop,
+        to_vec: Self::to_vec,
+        is_unique: Self::is_unique,
+        is_writable: Self::is_unique,
+    };
+
+    unsafe fn clone(_ptr: *mut u8, _cap: usize) {}
+
+    unsafe fn drop(_ptr: *mut u8, _cap: usize) {}
+
+    unsafe fn to_vec(ptr: *mut u8, _cap: usize, offset: usize, len: usize) -> Vec<u8> {
+        unsafe { copy_to_vec(ptr.add(offset), len) }
+    }
+
+    unsafe fn is_unique(_ptr: *mut u8, _cap: usize) -> bool {
+        // static storage may never be mutated
+        false
+    }
+
+}
+
+/// Storage which is owned by an external header, see `ExternalHeader`.
+/// This is the vtable of owners that don't provide operations of their own.
+pub(crate) struct ExternalStorage<RC: RefCnt>(PhantomData<RC>);
+
+impl<RC: RefCnt> ExternalStorage<RC> {
+
+    pub(crate) const VTABLE: &'static StorageVTable = &StorageVTable {
+        clone: Self::clone,
+        split: Self::clone,
+        drop: Self::drop,
+        to_vec: Self::to_vec,
+        is_unique: Self::is_unique,
+        is_writable: Self::is_unique,
+    };
+
+    unsafe fn clone(ptr: *mut u8, _cap: usize) {
+        increment_ref_cnt::<RC>(unsafe { &(*ptr.cast::<Metadata>()).ref_cnt });
+    }
+
+    unsafe fn drop(ptr: *mut u8, _cap: usize) {
+        if unsafe { release_ref::<RC>(ptr.cast::<Metadata>()) } {
+            unsafe { ExternalHeader::release(ptr.cast::<ExternalHeader>()); }
+        }
+    }
+
+    unsafe fn to_vec(ptr: *mut u8, cap: usize, offset: usize, len: usize) -> Vec<u8> {
+        let ret = unsafe { copy_to_vec(ExternalHeader::data(ptr).add(offset), len) };
+        unsafe { Self::drop(ptr, cap); }
+        ret
+    }
+
+    unsafe fn is_unique(_ptr: *mut u8, _cap: usize) -> bool {
+        // external storage is read-only
+        false
+    }
+
+}
+
+/// External storage which owns a vec, see `vec_header`.
+pub(crate) struct VecStorage<RC: RefCnt>(PhantomData<RC>);
+
+impl<RC: RefCnt> VecStorage<RC> {
+
+    pub(crate) const VTABLE: &'static StorageVTable = &StorageVTable {
+        clone: ExternalStorage::<RC>::clone,
+        split: ExternalStorage::<RC>::clone,
+        drop: ExternalStorage::<RC>::drop,
+        to_vec: Self::to_vec,
+        is_unique: ExternalStorage::<RC>::is_unique,
+        is_writable: ExternalStorage::<RC>::is_unique,
+    };
+
+    unsafe fn to_vec(ptr: *mut u8, cap: usize, offset: usize, len: usize) -> Vec<u8> {
+        // the vec can be handed out again if nobody else observes it
+        if offset == 0 && unsafe { claim_unique::<RC>(ptr.cast::<Metadata>()) } {
+            let mut vec = unsafe { take_vec(ptr.cast::<ExternalHeader>()) };
+            vec.truncate(len);
+            return vec;
+        }
+        unsafe { ExternalStorage::<RC>::to_vec(ptr, cap, offset, len) }
+    }
+
+}
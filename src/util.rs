@@ -1,20 +1,40 @@
 use std::{alloc, ptr};
+use std::cell::Cell;
 use std::alloc::{alloc, alloc_zeroed, Layout};
-use std::mem::{align_of, size_of, transmute};
+use std::mem::{align_of, size_of};
+use std::hint::spin_loop;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use crate::ref_cnt::RefCnt;
+use crate::stats;
 
 pub(crate) fn alloc_zeroed_buffer(len: usize) -> *mut u8 {
     let alloc = unsafe { alloc_zeroed(Layout::array::<u8>(len).unwrap()) };
     if alloc.is_null() {
         alloc_failure();
     }
+    stats::on_alloc(len);
     alloc
 }
 
 pub(crate) unsafe fn alloc_uninit_buffer(len: usize) -> *mut u8 {
-    let alloc = unsafe { alloc(Layout::array::<u8>(len).unwrap()) };
+    let alloc = cached_alloc(len);
     if alloc.is_null() {
         alloc_failure();
     }
+    stats::on_alloc(len);
+    alloc
+}
+
+/// SAFETY: `align` has to be a power of two.
+pub(crate) unsafe fn alloc_uninit_buffer_aligned(len: usize, align: usize) -> *mut u8 {
+    if align == 1 {
+        return unsafe { alloc_uninit_buffer(len) };
+    }
+    let alloc = unsafe { alloc(Layout::from_size_align(len, align).unwrap()) };
+    if alloc.is_null() {
+        alloc_failure();
+    }
+    stats::on_alloc(len);
     alloc
 }
 
@@ -24,101 +44,467 @@ fn alloc_failure() {
 
 #[inline]
 pub(crate) unsafe fn dealloc(ptr: *mut u8, len: usize) {
-    unsafe { alloc::dealloc(ptr, Layout::from_size_align_unchecked(len, 1)); }
+    unsafe { dealloc_aligned(ptr, len, 1); }
 }
 
 #[inline]
-pub(crate) const fn find_sufficient_cap<const GROWTH_FACTOR: usize>(curr: usize, req: usize) -> usize {
-    let mut curr = curr;
-    loop {
-        if curr >= req {
-            return curr;
+pub(crate) unsafe fn dealloc_aligned(ptr: *mut u8, len: usize, align: usize) {
+    stats::on_dealloc(len);
+    if align == 1 && cache_dealloc(ptr, len) {
+        return;
+    }
+    unsafe { alloc::dealloc(ptr, Layout::from_size_align_unchecked(len, align)); }
+}
+
+/// the smallest and largest allocation sizes served by the thread local cache,
+/// these are the sizes buffers allocate right after outgrowing the inline space.
+const MIN_CACHED_SIZE: usize = 32;
+const MAX_CACHED_SIZE: usize = 256;
+/// the maximum number of free allocations kept per size class.
+const MAX_CACHED_PER_CLASS: usize = 32;
+/// the number of slots of the table in which a thread remembers the allocations it handed
+/// out, only these are taken back into its cache once they get freed. A pointer may be stored
+/// in `TRACKED_PROBES` slots starting at its hashed one, if all of them are taken it replaces
+/// the first one. So once a thread holds more than a few hundred cacheable allocations, some
+/// of them are freed through the global allocator instead of being cached.
+const TRACKED_SLOTS: usize = 256;
+const TRACKED_PROBES: usize = 8;
+
+/// returns the sorted sizes the thread local cache serves and their count. allocations
+/// are only cached if they have exactly one of these sizes, as they have to be freed with
+/// the size they were allocated with. these are the capacities buffers reach by growing
+/// from a power of two through `Doubling` or `OneAndHalf`.
+const fn cached_sizes() -> ([usize; 32], usize) {
+    let mut sizes = [0; 32];
+    let mut cnt = 0;
+    let mut start = MIN_CACHED_SIZE;
+    while start <= MAX_CACHED_SIZE {
+        // the steps of `OneAndHalf`, the first one is also a step of `Doubling`
+        let mut size = start;
+        while size <= MAX_CACHED_SIZE {
+            // keep the sizes sorted and unique
+            let mut idx = cnt;
+            while idx > 0 && sizes[idx - 1] > size {
+                idx -= 1;
+            }
+            if idx == 0 || sizes[idx - 1] != size {
+                let mut i = cnt;
+                while i > idx {
+                    sizes[i] = sizes[i - 1];
+                    i -= 1;
+                }
+                sizes[idx] = size;
+                cnt += 1;
+            }
+            size += size / 2;
         }
-        curr *= GROWTH_FACTOR;
+        start *= 2;
     }
+    (sizes, cnt)
+}
+
+const SIZE_CLASSES: usize = cached_sizes().1;
+const CACHED_SIZES: [usize; 32] = cached_sizes().0;
+
+/// a free list of allocations per size class, the pointer to the next free
+/// allocation is stored in the first bytes of each free allocation.
+struct AllocCache {
+    heads: [Cell<*mut u8>; SIZE_CLASSES],
+    lens: [Cell<usize>; SIZE_CLASSES],
+    /// a hash table of the allocations handed out by this thread, frees of other
+    /// allocations aren't cached so memory doesn't pile up in threads that only free it.
+    tracked: [Cell<*mut u8>; TRACKED_SLOTS],
+}
+
+impl AllocCache {
+
+    /// returns the slots of `tracked` that may hold `ptr`.
+    #[inline]
+    fn tracked_slots(&self, ptr: *mut u8) -> impl Iterator<Item = &Cell<*mut u8>> {
+        // the multiplicative hash spreads allocations evenly even if they are laid out at a fixed stride
+        const FIB: u64 = 0x9E37_79B9_7F4A_7C15;
+        let slot = ((ptr as usize as u64).wrapping_mul(FIB) >> (u64::BITS - TRACKED_SLOTS.trailing_zeros())) as usize;
+        (0..TRACKED_PROBES).map(move |i| &self.tracked[(slot + i) % TRACKED_SLOTS])
+    }
+
+    /// remembers that this thread handed out `ptr`.
+    #[inline]
+    fn track(&self, ptr: *mut u8) {
+        let mut slots = self.tracked_slots(ptr);
+        let first = slots.next().unwrap();
+        let free = if first.get().is_null() {
+            first
+        } else {
+            // the allocation whose slot gets taken over won't be cached once it's freed
+            slots.find(|tracked| tracked.get().is_null()).unwrap_or(first)
+        };
+        free.set(ptr);
+    }
+
+    /// forgets `ptr` and returns whether this thread handed it out.
+    #[inline]
+    fn untrack(&self, ptr: *mut u8) -> bool {
+        match self.tracked_slots(ptr).find(|tracked| tracked.get() == ptr) {
+            Some(tracked) => {
+                tracked.set(ptr::null_mut());
+                true
+            }
+            None => false,
+        }
+    }
+
+}
+
+impl Drop for AllocCache {
+    fn drop(&mut self) {
+        for (class, head) in self.heads.iter().enumerate() {
+            let size = CACHED_SIZES[class];
+            let mut curr = head.get();
+            while !curr.is_null() {
+                let next = unsafe { curr.cast::<*mut u8>().read_unaligned() };
+                unsafe { alloc::dealloc(curr, Layout::from_size_align_unchecked(size, 1)); }
+                curr = next;
+            }
+        }
+    }
+}
+
+thread_local! {
+    static ALLOC_CACHE: AllocCache = const {
+        AllocCache {
+            heads: [const { Cell::new(ptr::null_mut()) }; SIZE_CLASSES],
+            lens: [const { Cell::new(0) }; SIZE_CLASSES],
+            tracked: [const { Cell::new(ptr::null_mut()) }; TRACKED_SLOTS],
+        }
+    };
 }
 
-/// this offsets dst by OFFSET if src and dst are equal.
-/// this implementation is special because it is fully branchless.
-///
-/// SAFETY: dst has to be greater than src.
-///         adding offset to dst has to produce a valid pointer.
 #[inline]
-pub(crate) unsafe fn offset_if_equal<const OFFSET: usize>(src: *mut u8, dst: *mut u8) -> *mut u8 {
-    let diff = dst as usize - src as usize;
-    let is_zero = (!diff).overflowing_add(1).1;
-    unsafe { dst.add(transmute::<bool, u8>(is_zero) as usize) }
+fn size_class(len: usize) -> Option<usize> {
+    if !(MIN_CACHED_SIZE..=MAX_CACHED_SIZE).contains(&len) {
+        return None;
+    }
+    CACHED_SIZES[..SIZE_CLASSES].binary_search(&len).ok()
 }
 
+/// returns a free allocation of `len` bytes from the current thread's cache or
+/// allocates a new one if there is none, cacheable allocations get tracked either way.
 #[inline]
-const fn align_to<const ALIGNMENT: usize>(val: usize) -> usize {
-    let additional = val % ALIGNMENT;
-    let diff = ALIGNMENT - additional;
-    val + diff
+fn cached_alloc(len: usize) -> *mut u8 {
+    let Some(class) = size_class(len) else {
+        return unsafe { alloc(Layout::array::<u8>(len).unwrap()) };
+    };
+    // the cache is unavailable while the thread is being torn down
+    ALLOC_CACHE.try_with(|cache| {
+        let head = cache.heads[class].get();
+        let ret = if head.is_null() {
+            unsafe { alloc(Layout::array::<u8>(len).unwrap()) }
+        } else {
+            cache.heads[class].set(unsafe { head.cast::<*mut u8>().read_unaligned() });
+            cache.lens[class].set(cache.lens[class].get() - 1);
+            head
+        };
+        if !ret.is_null() {
+            cache.track(ret);
+        }
+        ret
+    }).unwrap_or_else(|_| unsafe { alloc(Layout::array::<u8>(len).unwrap()) })
+}
+
+/// returns the number of free allocations of `len` bytes in the current thread's cache.
+#[cfg(test)]
+pub(crate) fn cached_cnt(len: usize) -> usize {
+    size_class(len).map_or(0, |class| ALLOC_CACHE.with(|cache| cache.lens[class].get()))
 }
 
+/// tries to put an allocation of `len` bytes with an alignment of 1 into the current
+/// thread's cache, returns whether the cache took it. only allocations this thread
+/// handed out and still tracks are taken, so frees from other threads are never cached.
 #[inline]
-pub(crate) fn align_unaligned_len_to<const ALIGNMENT: usize>(ptr: *mut u8, len: usize) -> usize {
-    let raw = ptr as usize;
-    let aligned = align_to::<ALIGNMENT>(raw);
-    let ptr_diff = aligned - raw;
-    if ptr_diff > len {
-        ptr_diff
-    } else {
-        ptr_diff + align_to::<ALIGNMENT>(len - ptr_diff)
-    }
+fn cache_dealloc(ptr: *mut u8, len: usize) -> bool {
+    let Some(class) = size_class(len) else {
+        return false;
+    };
+    ALLOC_CACHE.try_with(|cache| {
+        if !cache.untrack(ptr) {
+            return false;
+        }
+        let cached = cache.lens[class].get();
+        if cached == MAX_CACHED_PER_CLASS {
+            return false;
+        }
+        unsafe { ptr.cast::<*mut u8>().write_unaligned(cache.heads[class].get()); }
+        cache.heads[class].set(ptr);
+        cache.lens[class].set(cached + 1);
+        true
+    }).unwrap_or(false)
+}
+
+/// deallocates a buffer that has metadata stored at its end, this respects the
+/// alignment the buffer was originally allocated with.
+///
+/// SAFETY: `ptr` has to point to the start of a counted allocation of `cap` bytes.
+#[inline]
+pub(crate) unsafe fn dealloc_counted(ptr: *mut u8, cap: usize) {
+    let meta = unsafe { meta_of(ptr, cap) };
+    // make sure weak buffers can't observe the allocation anymore
+    unsafe { (*meta).detach_weak(); }
+    let align = unsafe { (*meta).align };
+    unsafe { dealloc_aligned(ptr, cap, align); }
 }
 
 #[inline]
 pub(crate) unsafe fn align_unaligned_ptr_to<const ALIGNMENT: usize, const REGION_SIZE: usize>(ptr: *mut u8, len: usize) -> *mut u8 {
     let end = ptr as usize + len;
     let additional = end % ALIGNMENT;
-    let region_start = ptr.add(len - (additional + REGION_SIZE));
-    region_start
+    ptr.add(len - (additional + REGION_SIZE))
 }
 
 #[inline]
 pub(crate) unsafe fn realloc_buffer_counted(buf: *mut u8, offset: usize, len: usize, new_cap: usize) -> *mut u8 {
-    let alloc = unsafe { alloc_uninit_buffer(new_cap) };
+    unsafe { realloc_buffer_counted_aligned(buf, offset, len, new_cap, 1) }
+}
+
+/// SAFETY: `align` has to be a power of two.
+#[inline]
+pub(crate) unsafe fn realloc_buffer_counted_aligned(buf: *mut u8, offset: usize, len: usize, new_cap: usize, align: usize) -> *mut u8 {
+    let alloc = unsafe { alloc_uninit_buffer_aligned(new_cap, align) };
     // copy the previous buffer into the newly allocated one
     unsafe { ptr::copy_nonoverlapping(buf.add(offset), alloc, len); }
 
     // setup metadata
-
-    let meta_ptr = unsafe { align_unaligned_ptr_to::<{ align_of::<usize>() }, METADATA_SIZE>(alloc, new_cap) };
-    assert_eq!(meta_ptr.cast::<usize>() as usize % 8, 0);
-    // set ref cnt
-    unsafe { *meta_ptr.cast::<usize>() = 1; }
+    unsafe { init_meta(alloc, new_cap, align); }
     alloc
 }
 
-/// this additional storage is used to store the reference counter and
-/// capacity and to align said values properly.
-const ADDITIONAL_SIZE: usize = METADATA_SIZE + align_of::<usize>() - 1;
-const METADATA_SIZE: usize = size_of::<usize>() * 2;
+/// the metadata consists of the reference counter followed by the alignment of the allocation,
+/// a pointer to the weak cell of the allocation and whether the views of its buffers overlap.
+pub(crate) const METADATA_SIZE: usize = size_of::<Metadata>();
 
+#[repr(C)]
+pub(crate) struct Metadata {
+    pub(crate) ref_cnt: AtomicUsize,
+    /// the alignment the allocation was created with, this is required to deallocate it again.
+    pub(crate) align: usize,
+    /// this is null until the first weak buffer referencing the allocation gets created.
+    pub(crate) weak: AtomicPtr<WeakCell>,
+    /// whether buffers viewing the same bytes reference the allocation. The disjoint parts
+    /// created by `split*` don't set this, so they can keep writing to their part in place.
+    pub(crate) overlapping: AtomicBool,
+}
+
+/// returns whether the spare capacity of an allocation of `cap` bytes with `len`
+/// used bytes is large enough to store properly aligned metadata at its end.
 #[inline]
-pub(crate) unsafe fn realloc_buffer(buf: *mut u8, offset: usize, len: usize, new_cap: usize) -> *mut u8 {
-    let alloc = unsafe { alloc_uninit_buffer(new_cap) };
-    // copy the previous buffer into the newly allocated one
-    unsafe { ptr::copy_nonoverlapping(buf.add(offset), alloc, len); }
-    alloc
+pub(crate) fn fits_meta(alloc: *mut u8, len: usize, cap: usize) -> bool {
+    let required = (alloc as usize + cap) % align_of::<usize>() + METADATA_SIZE;
+    cap - len >= required
 }
 
+/// SAFETY: `alloc` has to point to the start of an allocation of `cap` bytes
+/// that reserves space for metadata at its end.
 #[inline]
-pub(crate) unsafe fn realloc_buffer_and_dealloc(buf: *mut u8, offset: usize, len: usize, old_cap: usize, new_cap: usize) -> *mut u8 {
-    let alloc = unsafe { realloc_buffer(buf, offset, len, new_cap) };
-    unsafe { dealloc(buf, old_cap); }
-    alloc
+pub(crate) unsafe fn meta_of(alloc: *mut u8, cap: usize) -> *mut Metadata {
+    let meta_ptr = unsafe { align_unaligned_ptr_to::<{ align_of::<usize>() }, METADATA_SIZE>(alloc, cap) };
+    debug_assert_eq!(meta_ptr as usize % align_of::<Metadata>(), 0);
+    meta_ptr.cast::<Metadata>()
 }
 
+impl Metadata {
+
+    /// sets up the metadata of a freshly allocated buffer with a ref cnt of 1.
+    ///
+    /// SAFETY: `meta_ptr` has to point to allocated and properly aligned storage
+    /// for the metadata.
+    #[inline]
+    pub(crate) unsafe fn init(meta_ptr: *mut u8, align: usize) {
+        unsafe { meta_ptr.cast::<Metadata>().write(Metadata {
+            ref_cnt: AtomicUsize::new(1),
+            align,
+            weak: AtomicPtr::new(ptr::null_mut()),
+            overlapping: AtomicBool::new(false),
+        }); }
+    }
+
+    /// records that buffers viewing the same bytes reference the storage, writers have
+    /// to copy their data from now on unless they hold the only reference.
+    #[inline]
+    pub(crate) fn mark_overlapping(&self) {
+        // avoid writing to the shared cache line if the flag is set already
+        if !self.overlapping.load(Ordering::Acquire) {
+            self.overlapping.store(true, Ordering::Release);
+        }
+    }
+
+    /// marks the storage as dead for all weak buffers referencing it.
+    ///
+    /// SAFETY: this may only be called once when the storage gets freed.
+    #[inline]
+    pub(crate) unsafe fn detach_weak(&self) {
+        let weak = self.weak.load(Ordering::Acquire);
+        if !weak.is_null() {
+            unsafe { WeakCell::detach(weak); }
+        }
+    }
+
+}
+
+/// SAFETY: see `meta_of`
+#[inline]
+pub(crate) unsafe fn init_meta(alloc: *mut u8, cap: usize, align: usize) {
+    unsafe { Metadata::init(meta_of(alloc, cap).cast::<u8>(), align); }
+}
+
+/// rounds the capacity of an allocation up to a multiple of its alignment, so allocations
+/// with a custom alignment consist of whole aligned blocks as e.g. direct I/O requires.
+#[inline]
+pub(crate) fn aligned_cap(cap: usize, align: usize) -> usize {
+    cap.next_multiple_of(align)
+}
+
+/// SAFETY: see `meta_of`
 #[inline]
-pub(crate) const fn min(left: usize, right: usize) -> usize {
-    if left < right {
-        left
-    } else {
-        right
+pub(crate) unsafe fn meta_align(alloc: *mut u8, cap: usize) -> usize {
+    unsafe { (*meta_of(alloc, cap)).align }
+}
+
+/// SAFETY: `meta` has to point to the metadata of a live allocation.
+#[inline]
+pub(crate) unsafe fn has_weak(meta: *mut Metadata) -> bool {
+    !unsafe { &*meta }.weak.load(Ordering::Acquire).is_null()
+}
+
+/// A side allocation which is shared between all weak buffers of an allocation
+/// and lets them observe whether said allocation is still alive.
+pub(crate) struct WeakCell {
+    /// the number of weak buffers referencing this cell plus one that is shared by all strong buffers.
+    weak_cnt: AtomicUsize,
+    /// whether the allocation is still alive, this may only be accessed while holding `lock`.
+    alive: AtomicBool,
+    /// this prevents the allocation from being freed while a weak buffer tries to upgrade.
+    lock: AtomicBool,
+}
+
+impl WeakCell {
+
+    /// returns the weak cell of the allocation the metadata belongs to while
+    /// creating it if it doesn't exist yet. The returned cell's weak cnt was
+    /// already incremented for the caller.
+    ///
+    /// SAFETY: `meta` has to point to the metadata of a live allocation.
+    pub(crate) unsafe fn acquire(meta: *mut Metadata) -> *const WeakCell {
+        let meta = unsafe { &*meta };
+        // upgraded buffers view the same bytes as the buffer the weak buffer was created from
+        meta.mark_overlapping();
+        let mut cell = meta.weak.load(Ordering::Acquire);
+        if cell.is_null() {
+            let new = Box::into_raw(Box::new(WeakCell {
+                // the strong buffers hold one reference together
+                weak_cnt: AtomicUsize::new(1),
+                alive: AtomicBool::new(true),
+                lock: AtomicBool::new(false),
+            }));
+            cell = match meta.weak.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => new,
+                Err(existing) => {
+                    // somebody else was faster than us
+                    drop(unsafe { Box::from_raw(new) });
+                    existing
+                }
+            };
+        }
+        unsafe { &*cell }.weak_cnt.fetch_add(1, Ordering::AcqRel);
+        cell
+    }
+
+    #[inline]
+    pub(crate) fn lock(&self) {
+        while self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            spin_loop();
+        }
+    }
+
+    #[inline]
+    pub(crate) fn unlock(&self) {
+        self.lock.store(false, Ordering::Release);
     }
+
+    /// SAFETY: this may only be called while holding the lock.
+    #[inline]
+    pub(crate) unsafe fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn increment(&self) {
+        self.weak_cnt.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// SAFETY: the caller has to own one of the cell's references.
+    pub(crate) unsafe fn release(cell: *const WeakCell) {
+        if unsafe { &*cell }.weak_cnt.fetch_sub(1, Ordering::AcqRel) == 1 {
+            drop(unsafe { Box::from_raw(cell.cast_mut()) });
+        }
+    }
+
+    /// marks the allocation as dead and releases the reference of the strong buffers.
+    ///
+    /// SAFETY: this may only be called once when the allocation gets freed.
+    unsafe fn detach(cell: *const WeakCell) {
+        let cell_ref = unsafe { &*cell };
+        cell_ref.lock();
+        cell_ref.alive.store(false, Ordering::Relaxed);
+        cell_ref.unlock();
+        unsafe { WeakCell::release(cell); }
+    }
+
+}
+
+/// serializes setting up the metadata of unique buffers which get cloned through
+/// a shared reference, this happens at most once per allocation.
+static SHARE_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// runs `f` while no other unique buffer gets shared through a shared reference.
+pub(crate) fn with_share_lock<R>(f: impl FnOnce() -> R) -> R {
+    while SHARE_LOCK.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        spin_loop();
+    }
+    let ret = f();
+    SHARE_LOCK.store(false, Ordering::Release);
+    ret
+}
+
+/// returns whether the caller holds the only strong reference to the allocation, if so
+/// weak buffers are prevented from upgrading from now on, so the allocation may be mutated.
+///
+/// SAFETY: `meta` has to point to the metadata of a live allocation.
+pub(crate) unsafe fn claim_unique<RC: RefCnt>(meta: *mut Metadata) -> bool {
+    let meta = unsafe { &*meta };
+    let weak = meta.weak.load(Ordering::Acquire);
+    if weak.is_null() {
+        return RC::load(&meta.ref_cnt) == 1;
+    }
+    let cell = unsafe { &*weak };
+    // hold the lock in order to prevent weak buffers from upgrading concurrently
+    cell.lock();
+    let unique = RC::load(&meta.ref_cnt) == 1;
+    if unique {
+        meta.weak.store(ptr::null_mut(), Ordering::Release);
+        cell.alive.store(false, Ordering::Relaxed);
+    }
+    cell.unlock();
+    if unique {
+        unsafe { WeakCell::release(weak); }
+    }
+    unique
+}
+
+#[inline]
+pub(crate) unsafe fn realloc_buffer(buf: *mut u8, offset: usize, len: usize, new_cap: usize) -> *mut u8 {
+    let alloc = unsafe { alloc_uninit_buffer(new_cap) };
+    // copy the previous buffer into the newly allocated one
+    unsafe { ptr::copy_nonoverlapping(buf.add(offset), alloc, len); }
+    alloc
 }
 
 pub(crate) const fn build_bit_mask(offset: usize, ones_cnt: usize) -> usize {
@@ -155,6 +541,6 @@ pub(crate) const fn greater_zero_ret_one(num: usize) -> usize {
     const MSB_OFF: usize = (usize::BITS - 1) as usize;
 
     // if num is 0, identity will have a value of 0 as all bits are 0, for other values, this will overflow.
-    let identity = 0_usize - num;
+    let identity = 0_usize.wrapping_sub(num);
     identity >> MSB_OFF
 }
\ No newline at end of file